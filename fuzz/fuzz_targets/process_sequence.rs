@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use processor::{Ledger, Transaction};
+
+fuzz_target!(|transactions: Vec<Transaction>| {
+    let mut ledger = Ledger::new();
+    for tx in transactions {
+        // Errors are expected, only panics and broken invariants are of interest
+        let _ = ledger.process_soft(tx);
+    }
+    assert_eq!(ledger.is_consistent(), Ok(()));
+});
@@ -0,0 +1,90 @@
+//! An asynchronous wrapper around [Ledger], for use in `tokio` applications.
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{Ledger, LedgerError, Transaction};
+
+/// A [Ledger] shared between asynchronous tasks, guarded by a [tokio::sync::Mutex].
+#[derive(Debug, Default)]
+pub struct AsyncLedger(tokio::sync::Mutex<Ledger>);
+
+impl AsyncLedger {
+    /// Create an empty [AsyncLedger].
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Process a single [Transaction], waiting for exclusive access to the ledger, see
+    /// [Ledger::process].
+    pub async fn process_async(&self, tx: Transaction) -> Result<(), LedgerError> {
+        self.0.lock().await.process(tx)
+    }
+
+    /// Serialize the ledger to a CSV file, see [Ledger::dump_csv]. The file is written
+    /// asynchronously, after releasing the lock on the ledger.
+    pub async fn dump_csv_async(&self, path: impl AsRef<std::path::Path>) -> csv::Result<()> {
+        let output = self.0.lock().await.dump_csv_to_string()?;
+        let mut file = tokio::fs::File::create(path).await?;
+        file.write_all(output.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Wait for exclusive access to the underlying [Ledger].
+    pub async fn lock(&self) -> tokio::sync::MutexGuard<'_, Ledger> {
+        self.0.lock().await
+    }
+
+    /// Unwrap the underlying [Ledger].
+    pub fn into_inner(self) -> Ledger {
+        self.0.into_inner()
+    }
+}
+
+impl From<Ledger> for AsyncLedger {
+    fn from(ledger: Ledger) -> Self {
+        Self(tokio::sync::Mutex::new(ledger))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ClientId, TxAmount, TxId};
+    use fpdec::{Dec, Decimal};
+
+    #[tokio::test]
+    async fn process_and_dump() {
+        let ledger = std::sync::Arc::new(AsyncLedger::new());
+        let tasks: Vec<_> = (1..=4)
+            .map(|id| {
+                let ledger = ledger.clone();
+                tokio::spawn(async move {
+                    ledger
+                        .process_async(Transaction::new_deposit(
+                            ClientId(id),
+                            TxId(id.into()),
+                            TxAmount(Dec!(1.5)),
+                        ))
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(
+            ledger
+                .process_async(Transaction::new_dispute(ClientId(1), TxId(5)))
+                .await,
+            Err(LedgerError::UnknownTx(ClientId(1), TxId(5)))
+        );
+
+        let path = std::env::temp_dir().join(format!("processor-async-{}.csv", std::process::id()));
+        ledger.dump_csv_async(&path).await.unwrap();
+        let actual = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(actual, ledger.lock().await.dump_csv_to_string().unwrap());
+        assert_eq!(actual.lines().count(), 5);
+    }
+}
@@ -1,40 +1,215 @@
+use std::ffi::{OsStr, OsString};
+
 use thiserror::Error;
 
-use processor::{Ledger, Transaction};
+use processor::{Ledger, LedgerError, Transaction};
 
 /// Any kind of error in the pipeline CSV parsing -> payment processing -> final state output.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("missing input file argument")]
     MissingFile,
+    #[error("input file '{}' does not exist", .0.to_string_lossy())]
+    FileNotFound(OsString),
+    #[error("missing value for argument '{0}'")]
+    MissingValue(&'static str),
+    #[error("unexpected argument '{}'", .0.to_string_lossy())]
+    UnexpectedArgument(OsString),
+    #[error("error during processing: {}: transaction {1}: {2}", .0.to_string_lossy())]
+    LedgerError(OsString, usize, LedgerError),
+    #[error("unknown output format '{}', expected 'csv' or 'json'", .0.to_string_lossy())]
+    UnknownFormat(OsString),
     #[error("error during CSV processing: {0}")]
     CsvError(#[from] csv::Error),
+    #[error("error during JSON output: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("error during output: {0}")]
+    IoError(#[from] std::io::Error),
 }
 
-fn main() -> Result<(), Error> {
-    let mut ledger = Ledger::new();
+impl Error {
+    /// The exit code of the processor for this error: `1` for usage and processing errors, `2`
+    /// when the input file does not exist, `3` for malformed CSV input.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::MissingFile
+            | Self::MissingValue(_)
+            | Self::UnexpectedArgument(_)
+            | Self::UnknownFormat(_)
+            | Self::LedgerError(..)
+            | Self::JsonError(_)
+            | Self::IoError(_) => 1,
+            Self::FileNotFound(_) => 2,
+            Self::CsvError(_) => 3,
+        }
+    }
+}
+
+/// The format used to output the final state of the accounts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+enum Format {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// The command line arguments of the processor.
+#[derive(Debug, Default)]
+struct Args {
+    /// The CSV files to read transactions from in order, "-" means stdin.
+    inputs: Vec<OsString>,
+    /// The file to write the final state to, stdout if none was given.
+    output: Option<OsString>,
+    /// The format of the output.
+    format: Format,
+    /// Whether to stop at the first failed transaction, exiting with an error.
+    exit_on_error: bool,
+    /// Whether to print a summary of the processing on stderr.
+    summary: bool,
+}
+
+/// Statistics about the processed transactions.
+#[derive(Debug, Default)]
+struct Summary {
+    processed: usize,
+    errors: usize,
+}
+
+impl Args {
+    fn parse() -> Result<Self, Error> {
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut format = Format::default();
+        let mut exit_on_error = false;
+        let mut summary = false;
 
-    let path = std::env::args_os()
         // Skip argv[0]
-        .skip(1)
-        // Expect a file name here
-        .next()
-        .ok_or(Error::MissingFile)?;
-
-    for (tx, index) in Transaction::configured_csv_reader_builder()
-        .from_path(path)?
-        .into_deserialize()
-        .zip(1..)
-    {
-        match ledger.process(tx?) {
-            // All errors are logged but should not stop processing
-            Err(err) => eprintln!("error during processing: transaction {}: {}", index, err),
-            _ => {}
+        let mut args = std::env::args_os().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "-o" || arg == "--output" {
+                output = Some(args.next().ok_or(Error::MissingValue("--output"))?);
+            } else if arg == "--format" {
+                let value = args.next().ok_or(Error::MissingValue("--format"))?;
+                format = if value == "csv" {
+                    Format::Csv
+                } else if value == "json" {
+                    Format::Json
+                } else {
+                    return Err(Error::UnknownFormat(value));
+                };
+            } else if arg == "--strict" {
+                exit_on_error = true;
+            } else if arg == "--skip-errors" {
+                exit_on_error = false;
+            } else if arg == "--summary" {
+                summary = true;
+            } else if arg != "-" && arg.to_string_lossy().starts_with('-') {
+                return Err(Error::UnexpectedArgument(arg));
+            } else {
+                inputs.push(arg);
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(Error::MissingFile);
         }
+
+        Ok(Self {
+            inputs,
+            output,
+            format,
+            exit_on_error,
+            summary,
+        })
     }
+}
 
-    let mut writer = csv::Writer::from_writer(std::io::stdout());
-    ledger.dump_csv(&mut writer)?;
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+/// Process all transactions from a CSV stream, keeping count of processed and failed ones.
+fn process_csv<R: std::io::Read>(
+    ledger: &mut Ledger,
+    name: &OsStr,
+    reader: R,
+    exit_on_error: bool,
+    summary: &mut Summary,
+) -> Result<(), Error> {
+    let mut reader = Transaction::configured_csv_reader_builder().from_reader(reader);
+    Transaction::validate_csv_headers(&mut reader)?;
+    for (tx, index) in Transaction::read_csv(&mut reader)?.zip(1..) {
+        let tx = tx?;
+        summary.processed += 1;
+        if let Err(err) = ledger.process(tx) {
+            summary.errors += 1;
+            // Errors are fatal in strict mode, otherwise they are logged but do not stop processing
+            if exit_on_error {
+                return Err(Error::LedgerError(name.to_owned(), index, err));
+            }
+            eprintln!(
+                "error during processing: {}: transaction {}: {}",
+                name.to_string_lossy(),
+                index,
+                err
+            )
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<(), Error> {
+    let args = Args::parse()?;
+
+    let mut ledger = Ledger::new();
+    let mut summary = Summary::default();
+
+    // Process all inputs in order into the same ledger
+    let result = args.inputs.iter().try_for_each(|input| {
+        // Read from stdin when given "-" as the file name
+        if input == "-" {
+            process_csv(
+                &mut ledger,
+                input,
+                std::io::stdin(),
+                args.exit_on_error,
+                &mut summary,
+            )
+        } else {
+            let file = std::fs::File::open(input).map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => Error::FileNotFound(input.clone()),
+                _ => err.into(),
+            })?;
+            process_csv(&mut ledger, input, file, args.exit_on_error, &mut summary)
+        }
+    });
+
+    if args.summary {
+        eprintln!(
+            "{} transactions processed, {} errors",
+            summary.processed, summary.errors
+        );
+    }
+    result?;
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    match args.format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            // Failing to write the output is not a malformed input error
+            ledger
+                .dump_csv(&mut writer)
+                .map_err(|err| Error::IoError(err.into()))?;
+            writer.flush()?;
+        }
+        Format::Json => ledger.dump_json_accounts(output)?,
+    }
 
     Ok(())
 }
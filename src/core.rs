@@ -1,14 +1,17 @@
 //! Core types used in the processing of payments.
 
-use fpdec::{Dec, Decimal};
+use fpdec::{CheckedAdd, Dec, Decimal, DecimalError};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
+use crate::{TxAmountError, TxAmountParseError};
+
 /// Clients are anonymous, identified by globally unique ids. "16-bit ought to be enough for
 /// anyone".
 #[derive(
     Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
 )]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(transparent)]
 pub struct ClientId(pub u16);
 
@@ -18,10 +21,19 @@ impl std::fmt::Display for ClientId {
     }
 }
 
+impl std::str::FromStr for ClientId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
 /// Transactions are identified by a globally unique id. 32 bit is sufficient for our puposes.
 #[derive(
     Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
 )]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(transparent)]
 pub struct TxId(pub u32);
 
@@ -31,6 +43,44 @@ impl std::fmt::Display for TxId {
     }
 }
 
+impl TxId {
+    /// The id following `current`, or `None` if it is the largest possible id.
+    pub fn next_after(current: TxId) -> Option<TxId> {
+        current.0.checked_add(1).map(Self)
+    }
+}
+
+/// A thread-safe generator of sequential [TxId]s.
+#[derive(Debug, Default)]
+pub struct TxIdGenerator(std::sync::atomic::AtomicU32);
+
+impl TxIdGenerator {
+    /// Create a generator whose first id follows `current`, e.g: the result of
+    /// [crate::Ledger::max_transaction_id].
+    pub fn new(current: TxId) -> Self {
+        Self(std::sync::atomic::AtomicU32::new(current.0))
+    }
+
+    /// Generate the next id, or `None` once all ids have been exhausted.
+    pub fn next(&self) -> Option<TxId> {
+        use std::sync::atomic::Ordering;
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                current.checked_add(1)
+            })
+            .ok()
+            .and_then(|current| TxId::next_after(TxId(current)))
+    }
+}
+
+impl std::str::FromStr for TxId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
 /// Amounts are represented as exact decimals, up to four places past the decimal.
 /// For ease of implementation, make use of [fpdec::Decimal] instead of implementing a custom
 /// fixed-point number.
@@ -41,6 +91,119 @@ pub struct TxAmount(#[serde_as(as = "DisplayFromStr")] pub Decimal);
 
 impl TxAmount {
     pub const ZERO: Self = Self(Dec!(0));
+    /// The largest amount that can be represented with four places past the decimal.
+    pub const MAX: Self = Self(Dec!(17014118346046923173168730371588410.5727));
+    /// The smallest amount that can be represented with four places past the decimal.
+    pub const MIN: Self = Self(Dec!(-17014118346046923173168730371588410.5727));
+
+    /// Convert a floating point value to a [TxAmount], rounded to four places past the decimal.
+    pub fn from_f64(v: f64) -> Result<Self, TxAmountError> {
+        // Reject values which could not be represented with four decimal places up-front, as
+        // `fpdec` can panic when converting large values
+        if v.is_finite() && v.abs() >= 1e34 {
+            return Err(TxAmountError::Overflow);
+        }
+        let value = Decimal::try_from(v).map_err(|err| match err {
+            DecimalError::InfiniteValue | DecimalError::NotANumber => TxAmountError::NotFinite,
+            _ => TxAmountError::Overflow,
+        })?;
+        Ok(Self(value).round_to_4dp())
+    }
+
+    /// Convert to the nearest floating point value, which may lose precision.
+    pub fn to_f64(self) -> f64 {
+        self.0.coefficient() as f64 / 10f64.powi(self.0.n_frac_digits().into())
+    }
+
+    /// Round to four places past the decimal, with ties going away from zero.
+    pub fn round_to_4dp(self) -> Self {
+        if self.is_within_precision() {
+            return self;
+        }
+        // Divide the coefficient directly, scaling the value up could overflow its representation
+        let divisor = 10_i128.pow(u32::from(self.0.n_frac_digits() - 4));
+        let coeff = self.0.coefficient();
+        let rounding = if (coeff % divisor).abs() * 2 >= divisor {
+            coeff.signum()
+        } else {
+            0
+        };
+        Self(Decimal::new_raw(coeff / divisor + rounding, 4))
+    }
+
+    /// Add two amounts, returning `None` if the result is outside of the [TxAmount::MIN] to
+    /// [TxAmount::MAX] range.
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Self)
+            .filter(|res| (Self::MIN..=Self::MAX).contains(res))
+    }
+
+    /// Add two amounts, saturating at [TxAmount::MIN] or [TxAmount::MAX] instead of overflowing.
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).unwrap_or(if rhs.is_negative() {
+            Self::MIN
+        } else {
+            Self::MAX
+        })
+    }
+
+    /// Sum amounts, saturating at [TxAmount::MIN] or [TxAmount::MAX] instead of overflowing.
+    /// Credits and debits are summed separately, so the result does not depend on the order of
+    /// the amounts, and is exact whenever neither of those sums saturates.
+    pub fn saturating_sum(amounts: impl IntoIterator<Item = Self>) -> Self {
+        let (credits, debits) =
+            amounts
+                .into_iter()
+                .fold((Self::ZERO, Self::ZERO), |(credits, debits), amount| {
+                    if amount.is_negative() {
+                        (credits, debits.saturating_add(amount))
+                    } else {
+                        (credits.saturating_add(amount), debits)
+                    }
+                });
+        credits.saturating_add(debits)
+    }
+
+    /// The absolute value of the amount.
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// Whether the amount is strictly greater than zero.
+    pub fn is_positive(self) -> bool {
+        self > Self::ZERO
+    }
+
+    /// Whether the amount is strictly less than zero.
+    pub fn is_negative(self) -> bool {
+        self < Self::ZERO
+    }
+
+    /// Whether the amount is equal to zero.
+    pub fn is_zero(self) -> bool {
+        self == Self::ZERO
+    }
+
+    /// Whether the amount has at most four places past the decimal.
+    pub fn is_within_precision(self) -> bool {
+        self.0.n_frac_digits() <= 4
+    }
+}
+
+/// Generate strictly positive amounts with four places past the decimal, as required by
+/// [crate::Deposit], [crate::Withdrawal], and [crate::Transfer].
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TxAmount {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let coeff: u64 = u.int_in_range(1..=u64::MAX)?;
+        Ok(Self(Decimal::new_raw(coeff.into(), 4)))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <u64 as arbitrary::Arbitrary>::size_hint(depth)
+    }
 }
 
 impl std::fmt::Display for TxAmount {
@@ -49,6 +212,23 @@ impl std::fmt::Display for TxAmount {
     }
 }
 
+impl std::str::FromStr for TxAmount {
+    type Err = TxAmountParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: Decimal = s
+            .parse()
+            .map_err(|_| TxAmountParseError::Invalid(s.into()))?;
+        if value.n_frac_digits() > 4 {
+            return Err(TxAmountParseError::TooPrecise);
+        }
+        if value < Decimal::ZERO {
+            return Err(TxAmountParseError::Negative);
+        }
+        Ok(Self(value))
+    }
+}
+
 impl std::ops::Add<TxAmount> for TxAmount {
     type Output = Self;
 
@@ -77,6 +257,35 @@ impl std::ops::SubAssign<TxAmount> for TxAmount {
     }
 }
 
+impl std::ops::Mul<u32> for TxAmount {
+    type Output = Self;
+
+    fn mul(self, rhs: u32) -> Self::Output {
+        Self(self.0 * Decimal::from(rhs))
+    }
+}
+
+/// The quotient is rounded to 4 decimal places, see [TxAmount::round_to_4dp].
+impl std::ops::Div<std::num::NonZeroU32> for TxAmount {
+    type Output = Self;
+
+    fn div(self, rhs: std::num::NonZeroU32) -> Self::Output {
+        Self(self.0 / Decimal::from(rhs.get())).round_to_4dp()
+    }
+}
+
+impl std::iter::Sum<TxAmount> for TxAmount {
+    fn sum<I: Iterator<Item = TxAmount>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |acc, amount| acc + amount)
+    }
+}
+
+impl<'a> std::iter::Sum<&'a TxAmount> for TxAmount {
+    fn sum<I: Iterator<Item = &'a TxAmount>>(iter: I) -> Self {
+        iter.cloned().sum()
+    }
+}
+
 impl std::ops::Neg for TxAmount {
     type Output = Self;
 
@@ -84,3 +293,242 @@ impl std::ops::Neg for TxAmount {
         Self(-self.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn amount_from_f64() {
+        assert_eq!(TxAmount::from_f64(1.5), Ok(TxAmount(Dec!(1.5))));
+        assert_eq!(TxAmount::from_f64(-0.1), Ok(TxAmount(Dec!(-0.1))));
+        assert_eq!(TxAmount::from_f64(1.23456), Ok(TxAmount(Dec!(1.2346))));
+        assert_eq!(TxAmount::from_f64(f64::NAN), Err(TxAmountError::NotFinite));
+        assert_eq!(
+            TxAmount::from_f64(f64::INFINITY),
+            Err(TxAmountError::NotFinite)
+        );
+        assert_eq!(TxAmount::from_f64(1e300), Err(TxAmountError::Overflow));
+    }
+
+    #[test]
+    fn amount_to_f64() {
+        assert_eq!(TxAmount(Dec!(1.5)).to_f64(), 1.5);
+        assert_eq!(TxAmount(Dec!(-0.25)).to_f64(), -0.25);
+        assert_eq!(TxAmount::ZERO.to_f64(), 0.0);
+        assert_eq!(TxAmount(Dec!(42)).to_f64(), 42.0);
+    }
+
+    #[test]
+    fn amount_round_to_4dp() {
+        assert_eq!(TxAmount(Dec!(1.5)).round_to_4dp(), TxAmount(Dec!(1.5)));
+        assert_eq!(
+            TxAmount(Dec!(1.12345)).round_to_4dp(),
+            TxAmount(Dec!(1.1235))
+        );
+        assert_eq!(
+            TxAmount(Dec!(1.12344)).round_to_4dp(),
+            TxAmount(Dec!(1.1234))
+        );
+        assert_eq!(
+            TxAmount(Dec!(-1.12345)).round_to_4dp(),
+            TxAmount(Dec!(-1.1235))
+        );
+        assert_eq!(
+            TxAmount(Dec!(-1.12344)).round_to_4dp(),
+            TxAmount(Dec!(-1.1234))
+        );
+        assert_eq!(
+            TxAmount(Dec!(0.000049999999999999)).round_to_4dp(),
+            TxAmount::ZERO
+        );
+        // Must not overflow the internal representation
+        assert_eq!(
+            TxAmount(Dec!(1701411834604692317316873037158841.05727)).round_to_4dp(),
+            TxAmount(Dec!(1701411834604692317316873037158841.0573))
+        );
+        assert!(TxAmount(Dec!(1.1234)).is_within_precision());
+        assert!(!TxAmount(Dec!(1.12345)).is_within_precision());
+    }
+
+    #[test]
+    fn amount_sign() {
+        let positive = TxAmount(Dec!(1.5));
+        let negative = TxAmount(Dec!(-1.5));
+        assert_eq!(negative.abs(), positive);
+        assert_eq!(positive.abs(), positive);
+        assert!(positive.is_positive() && !positive.is_negative() && !positive.is_zero());
+        assert!(!negative.is_positive() && negative.is_negative() && !negative.is_zero());
+        assert!(!TxAmount::ZERO.is_positive() && !TxAmount::ZERO.is_negative());
+        assert!(TxAmount(Dec!(0.0)).is_zero());
+    }
+
+    #[test]
+    fn amount_bounds() {
+        assert!(TxAmount::MAX.is_within_precision());
+        assert_eq!(TxAmount::MIN, -TxAmount::MAX);
+        assert!(TxAmount::MIN < TxAmount::ZERO && TxAmount::ZERO < TxAmount::MAX);
+        assert_eq!(TxAmount::MAX.checked_add(TxAmount(Dec!(0.0001))), None);
+        assert_eq!(TxAmount::MIN.checked_add(TxAmount(Dec!(-0.0001))), None);
+        assert_eq!(
+            TxAmount::MAX.checked_add(TxAmount::MIN),
+            Some(TxAmount::ZERO)
+        );
+    }
+
+    #[test]
+    fn amount_mul_div() {
+        assert_eq!(TxAmount(Dec!(1.5)) * 3, TxAmount(Dec!(4.5)));
+        let three = std::num::NonZeroU32::new(3).unwrap();
+        assert_eq!(TxAmount(Dec!(4.5)) / three, TxAmount(Dec!(1.5)));
+        assert_eq!(TxAmount(Dec!(1)) / three, TxAmount(Dec!(0.3333)));
+        assert_eq!(TxAmount(Dec!(2)) / three, TxAmount(Dec!(0.6667)));
+        assert_eq!(TxAmount(Dec!(-2)) / three, TxAmount(Dec!(-0.6667)));
+    }
+
+    #[test]
+    fn amount_sum() {
+        let amounts = [
+            TxAmount(Dec!(1.5)),
+            TxAmount(Dec!(-0.25)),
+            TxAmount(Dec!(2)),
+        ];
+        assert_eq!(amounts.iter().sum::<TxAmount>(), TxAmount(Dec!(3.25)));
+        assert_eq!(amounts.into_iter().sum::<TxAmount>(), TxAmount(Dec!(3.25)));
+        assert_eq!(
+            std::iter::empty::<TxAmount>().sum::<TxAmount>(),
+            TxAmount::ZERO
+        );
+    }
+
+    #[test]
+    fn saturating_sum() {
+        let one = TxAmount(Dec!(1));
+        assert_eq!(TxAmount::MAX.saturating_add(one), TxAmount::MAX);
+        assert_eq!(TxAmount::MIN.saturating_add(-one), TxAmount::MIN);
+        assert_eq!(TxAmount::MAX.saturating_add(-one), TxAmount::MAX - one);
+        assert_eq!(
+            TxAmount::saturating_sum([one, TxAmount(Dec!(-0.25)), one]),
+            TxAmount(Dec!(1.75))
+        );
+        assert_eq!(TxAmount::saturating_sum([]), TxAmount::ZERO);
+        assert_eq!(
+            TxAmount::saturating_sum([TxAmount::MAX, TxAmount::MAX]),
+            TxAmount::MAX
+        );
+        assert_eq!(
+            TxAmount::saturating_sum([TxAmount::MIN, TxAmount::MIN]),
+            TxAmount::MIN
+        );
+        // The order of the amounts does not matter
+        assert_eq!(
+            TxAmount::saturating_sum([TxAmount::MAX, one, -one]),
+            TxAmount::saturating_sum([-one, TxAmount::MAX, one]),
+        );
+    }
+
+    #[test]
+    fn id_from_str() {
+        assert_eq!("42".parse(), Ok(ClientId(42)));
+        assert_eq!("42".parse(), Ok(TxId(42)));
+        assert!("65536".parse::<ClientId>().is_err());
+        assert_eq!("4294967295".parse(), Ok(TxId(u32::MAX)));
+        assert!("-1".parse::<TxId>().is_err());
+        assert!("abc".parse::<ClientId>().is_err());
+    }
+
+    #[test]
+    fn tx_id_next_after() {
+        assert_eq!(TxId::next_after(TxId(41)), Some(TxId(42)));
+        assert_eq!(TxId::next_after(TxId(u32::MAX)), None);
+    }
+
+    #[test]
+    fn tx_id_generator() {
+        let generator = TxIdGenerator::default();
+        assert_eq!(generator.next(), Some(TxId(1)));
+        assert_eq!(generator.next(), Some(TxId(2)));
+
+        let generator = TxIdGenerator::new(TxId(u32::MAX - 1));
+        assert_eq!(generator.next(), Some(TxId(u32::MAX)));
+        assert_eq!(generator.next(), None);
+        assert_eq!(generator.next(), None);
+
+        let generator = TxIdGenerator::new(TxId(0));
+        let ids: std::collections::BTreeSet<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..100)
+                            .map(|_| generator.next().unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        assert_eq!(ids.len(), 400);
+        assert_eq!(ids.iter().next_back(), Some(&TxId(400)));
+    }
+
+    #[test]
+    fn amount_from_str() {
+        assert_eq!("3.14".parse(), Ok(TxAmount(Dec!(3.14))));
+        assert_eq!("42".parse(), Ok(TxAmount(Dec!(42))));
+        assert_eq!("0.0001".parse(), Ok(TxAmount(Dec!(0.0001))));
+        assert_eq!(
+            "0.00001".parse::<TxAmount>(),
+            Err(TxAmountParseError::TooPrecise)
+        );
+        assert_eq!(
+            "-1.0".parse::<TxAmount>(),
+            Err(TxAmountParseError::Negative)
+        );
+        assert_eq!(
+            "abc".parse::<TxAmount>(),
+            Err(TxAmountParseError::Invalid("abc".into()))
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptest_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Amounts with four places past the decimal, small enough that sums cannot overflow
+    fn amount() -> impl Strategy<Value = TxAmount> {
+        (-1_000_000_000_000_000_i64..1_000_000_000_000_000)
+            .prop_map(|coeff| TxAmount(Decimal::new_raw(coeff.into(), 4)))
+    }
+
+    proptest! {
+        #[test]
+        fn add_then_sub(a in amount(), b in amount()) {
+            prop_assert_eq!((a + b) - b, a);
+        }
+
+        #[test]
+        fn add_commutes(a in amount(), b in amount()) {
+            prop_assert_eq!(a + b, b + a);
+        }
+
+        #[test]
+        fn double_neg(a in amount()) {
+            prop_assert_eq!(-(-a), a);
+        }
+
+        #[test]
+        fn add_zero(a in amount()) {
+            prop_assert_eq!(a + TxAmount::ZERO, a);
+        }
+
+        #[test]
+        fn sub_self(a in amount()) {
+            prop_assert_eq!(a - a, TxAmount::ZERO);
+        }
+    }
+}
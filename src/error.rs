@@ -1,7 +1,7 @@
 //! Error types for this crate.
 use thiserror::Error;
 
-use crate::{ClientId, TxId};
+use crate::{ClientId, TxAmount, TxId};
 
 /// Any kind of error that can happen when processing a [crate::Transaction] in a [crate::Ledger].
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
@@ -10,19 +10,264 @@ pub enum LedgerError {
     NotEnoughFunds,
     #[error("unknown transaction with user '{0}', id '{1}'")]
     UnknownTx(ClientId, TxId),
+    #[error("unknown user '{0}'")]
+    UnknownClient(ClientId),
     #[error("transaction has already been disputed")]
     AlreadyDisputed,
     #[error("transaction is not currently disputed")]
     NotDisputed,
     #[error("account is frozen")]
     FrozenAccount,
+    #[error("transaction would overflow the account's balance")]
+    Overflow,
+    #[error("inconsistent records for transaction with user '{0}', id '{1}'")]
+    InconsistentTx(ClientId, TxId),
+    #[error("cannot transfer funds to the same account")]
+    SelfTransfer,
+    #[error("conflicting transaction with user '{0}', id '{1}'")]
+    ConflictingTx(ClientId, TxId),
+    #[error("duplicate transaction with user '{0}', id '{1}'")]
+    DuplicateTx(ClientId, TxId),
+    #[error("user '{0}' already exists")]
+    ClientAlreadyExists(ClientId),
+    #[error("credit limit cannot be negative")]
+    NegativeCreditLimit,
+    #[error("transaction amount must be strictly positive")]
+    NonPositiveAmount,
+    #[error("I/O error")]
+    IoError(#[from] SharedIoError),
+    #[error("{msg}")]
+    Context {
+        msg: String,
+        #[source]
+        source: Box<LedgerError>,
+    },
 }
 
-/// Any kind of error that can happen when deserializing a [crate::Transaction] value.
+impl From<std::io::Error> for LedgerError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err.into())
+    }
+}
+
+/// Add context to the error of a [Result], see [LedgerError::Context].
+pub trait LedgerErrorExt<T> {
+    fn context(self, msg: &str) -> Result<T, LedgerError>;
+}
+
+impl<T, E: Into<LedgerError>> LedgerErrorExt<T> for Result<T, E> {
+    fn context(self, msg: &str) -> Result<T, LedgerError> {
+        self.map_err(|err| LedgerError::Context {
+            msg: msg.into(),
+            source: Box::new(err.into()),
+        })
+    }
+}
+
+/// A cheaply clonable [std::io::Error], compared through its kind and message, so that
+/// [LedgerError] can keep its derived traits.
+#[derive(Clone, Debug, Error)]
+#[error(transparent)]
+pub struct SharedIoError(std::sync::Arc<std::io::Error>);
+
+impl SharedIoError {
+    /// The underlying [std::io::Error].
+    pub fn inner(&self) -> &std::io::Error {
+        &self.0
+    }
+
+    fn key(&self) -> (std::io::ErrorKind, String) {
+        (self.0.kind(), self.0.to_string())
+    }
+}
+
+impl From<std::io::Error> for SharedIoError {
+    fn from(err: std::io::Error) -> Self {
+        Self(std::sync::Arc::new(err))
+    }
+}
+
+impl PartialEq for SharedIoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SharedIoError {}
+
+impl PartialOrd for SharedIoError {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SharedIoError {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let (kind, msg) = self.key();
+        let (other_kind, other_msg) = other.key();
+        // `ErrorKind` does not implement `Ord`, compare its debug representation instead
+        (format!("{:?}", kind), msg).cmp(&(format!("{:?}", other_kind), other_msg))
+    }
+}
+
+impl std::hash::Hash for SharedIoError {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl LedgerError {
+    /// Whether the same operation could succeed if it was attempted again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LedgerError::NotEnoughFunds
+            | LedgerError::UnknownTx(..)
+            | LedgerError::UnknownClient(_)
+            | LedgerError::AlreadyDisputed
+            | LedgerError::NotDisputed
+            | LedgerError::FrozenAccount
+            | LedgerError::Overflow
+            | LedgerError::SelfTransfer
+            | LedgerError::ConflictingTx(..)
+            | LedgerError::InconsistentTx(..)
+            | LedgerError::DuplicateTx(..)
+            | LedgerError::ClientAlreadyExists(_)
+            | LedgerError::NegativeCreditLimit
+            | LedgerError::NonPositiveAmount => false,
+            LedgerError::IoError(_) => true,
+            LedgerError::Context { source, .. } => source.is_retryable(),
+        }
+    }
+
+    /// Whether the error is caused by the input data being invalid for the current state of the
+    /// ledger, meaning that retrying the same operation would fail again.
+    pub fn is_data_error(&self) -> bool {
+        match self {
+            LedgerError::NotEnoughFunds
+            | LedgerError::UnknownTx(..)
+            | LedgerError::UnknownClient(_)
+            | LedgerError::AlreadyDisputed
+            | LedgerError::NotDisputed
+            | LedgerError::FrozenAccount
+            | LedgerError::Overflow
+            | LedgerError::SelfTransfer
+            | LedgerError::ConflictingTx(..)
+            | LedgerError::InconsistentTx(..)
+            | LedgerError::DuplicateTx(..)
+            | LedgerError::ClientAlreadyExists(_)
+            | LedgerError::NegativeCreditLimit
+            | LedgerError::NonPositiveAmount => true,
+            LedgerError::IoError(_) => false,
+            LedgerError::Context { source, .. } => source.is_data_error(),
+        }
+    }
+}
+
+/// A broken internal invariant of a [crate::Ledger], see [crate::Ledger::is_consistent].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Error)]
+pub enum InconsistencyError {
+    #[error("transaction with user '{0}', id '{1}' has no corresponding account")]
+    MissingAccount(ClientId, TxId),
+    #[error("transaction with user '{0}', id '{1}' has no recorded amount")]
+    MissingAmount(ClientId, TxId),
+    #[error("transaction with user '{0}', id '{1}' has no recorded state")]
+    MissingState(ClientId, TxId),
+    #[error("held funds of user '{client}' are {held}, below the {expected} of its disputes")]
+    HeldFundsTooLow {
+        client: ClientId,
+        held: TxAmount,
+        expected: TxAmount,
+    },
+}
+
+/// Any kind of error that can happen when deserializing a [crate::Transaction] or
+/// a [crate::TxState] value.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
 pub enum ParseError {
-    #[error("amount not provided")]
-    MissingAmount,
+    #[error("amount not provided for '{type_}' transaction on row {row}")]
+    MissingAmount { type_: String, row: usize },
+    #[error("amount must be strictly positive for '{type_}' transaction on row {row}")]
+    NonPositiveAmount { type_: String, row: usize },
+    #[error("transfer destination not provided on row {row}")]
+    MissingDestination { row: usize },
     #[error("unknown transaction type '{0}'")]
     UnknownTx(String),
+    #[error("unknown transaction state '{0}'")]
+    UnknownTxState(String),
+    #[error("wrong CSV headers, expected {expected:?}, got {got:?}")]
+    WrongHeaders {
+        expected: Vec<String>,
+        got: Vec<String>,
+    },
+}
+
+/// Any kind of error that can happen when processing raw transaction fields, see
+/// [crate::Ledger::process_transaction_fields].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
+pub enum ProcessError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Amount(#[from] TxAmountParseError),
+    #[error(transparent)]
+    Ledger(#[from] LedgerError),
+}
+
+/// Any kind of error that can happen when saving or loading a [crate::Ledger] to a file, see
+/// [crate::Ledger::save_to_path] and [crate::Ledger::load_from_path].
+#[derive(Debug, Error)]
+pub enum LedgerPersistenceError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Processing did not complete in time, see [crate::Ledger::process_with_timeout].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
+#[error("timed out after processing {processed} transactions")]
+pub struct TimeoutError {
+    /// The number of transactions processed before the timeout.
+    pub processed: usize,
+}
+
+/// Any kind of error that can happen when converting a value to a [crate::TxAmount].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
+pub enum TxAmountError {
+    #[error("amount is not a finite number")]
+    NotFinite,
+    #[error("amount is out of the representable range")]
+    Overflow,
+}
+
+/// Any kind of error that can happen when parsing a [crate::TxAmount] from a string.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
+pub enum TxAmountParseError {
+    #[error("invalid amount '{0}'")]
+    Invalid(String),
+    #[error("amount has more than four decimal places")]
+    TooPrecise,
+    #[error("amount is negative")]
+    Negative,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn error_source_chain() {
+        let res: Result<(), std::io::Error> = Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no such file",
+        ));
+        let err = res.context("loading ledger").unwrap_err();
+        assert_eq!(err.to_string(), "loading ledger");
+        assert!(err.is_retryable());
+        assert!(!err.is_data_error());
+        let io_err = err.source().unwrap();
+        assert_eq!(io_err.to_string(), "I/O error");
+        assert_eq!(io_err.source().unwrap().to_string(), "no such file");
+    }
 }
@@ -1,23 +1,362 @@
 //! A ledger implementation to track all transactions.
 
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
 use crate::{
-    Chargeback, ClientId, Deposit, Dispute, LedgerError, Resolve, Transaction, TxAmount, TxId,
-    Withdrawal,
+    Chargeback, ClientId, Deposit, Dispute, InconsistencyError, LedgerError,
+    LedgerPersistenceError, ParseError, ProcessError, Resolve, TimeoutError, Transaction, Transfer,
+    TxAmount, TxId, Withdrawal,
 };
 
 /// A ledger of accounts, which processes transactions one at a time.
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[serde_as]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Ledger {
     accounts: std::collections::HashMap<ClientId, AccountInfo>,
+    // JSON does not support non-string keys, serialize as a list of key-value pairs instead
+    #[serde_as(as = "Vec<(_, _)>")]
     transaction_amounts: std::collections::HashMap<(ClientId, TxId), TxAmount>,
+    #[serde_as(as = "Vec<(_, _)>")]
     transaction_state: std::collections::HashMap<(ClientId, TxId), TxState>,
+    // Transactions forgotten by `evict_finalized_transactions`, kept to reject duplicates
+    #[serde(default)]
+    evicted_transactions: std::collections::HashSet<(ClientId, TxId)>,
+    error_policy: ErrorPolicy,
+    admin_log: Vec<AdminAction>,
+    history: std::collections::HashMap<ClientId, Vec<Transaction>>,
+    // The `creation_order` of the next account to be created
+    #[serde(default)]
+    next_creation_order: u64,
+    // Number of failed transactions during the last call to `extend`
+    #[serde(skip)]
+    last_extend_errors: usize,
+    #[serde(skip)]
+    observers: Observers,
+}
+
+/// A callback interface to react to the transactions processed by a [Ledger], see
+/// [Ledger::subscribe]. Observers must be unwind safe, so that a [Ledger] can still be used with
+/// [std::panic::catch_unwind].
+pub trait LedgerObserver: Send + Sync + std::panic::UnwindSafe + std::panic::RefUnwindSafe {
+    /// Called after each transaction is processed, whether it succeeded or not. The changes made
+    /// by the transaction have been committed, or rolled back, by the time it is called.
+    fn on_transaction(&self, tx: &Transaction, result: &Result<(), LedgerError>);
+}
+
+/// An identifier for a [LedgerObserver] subscribed to a [Ledger], see [Ledger::unsubscribe].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObserverId(usize);
+
+// The observers subscribed to a [Ledger]. They are not part of its state: they are ignored for
+// comparisons, and are not carried over to clones.
+#[derive(Default)]
+struct Observers {
+    next_id: usize,
+    observers: Vec<(ObserverId, Box<dyn LedgerObserver>)>,
+}
+
+impl Observers {
+    fn notify(&self, tx: &Transaction, result: &Result<(), LedgerError>) {
+        for (_, observer) in self.observers.iter() {
+            observer.on_transaction(tx, result);
+        }
+    }
+}
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Default::default()
+    }
+}
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Observers")
+            .field("len", &self.observers.len())
+            .finish()
+    }
+}
+
+impl PartialEq for Observers {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Observers {}
+
+/// An administrative action taken on a [Ledger], recorded for auditing purposes.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum AdminAction {
+    /// A frozen account was unlocked, see [Ledger::admin_unlock].
+    Unlock { client: ClientId, reason: String },
+}
+
+/// How a [Ledger] should behave when processing a batch of transactions results in an error.
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+pub enum ErrorPolicy {
+    /// Stop processing at the first error.
+    Strict,
+    /// Ignore the failed transaction and keep processing.
+    #[default]
+    Soft,
+}
+
+/// A builder to configure a [Ledger] before its first use.
+#[must_use]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LedgerBuilder {
+    n_clients: usize,
+    n_transactions: usize,
+    error_policy: ErrorPolicy,
+}
+
+impl LedgerBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Pre-allocate space for the given number of clients and transactions.
+    pub fn with_capacity(mut self, n_clients: usize, n_transactions: usize) -> Self {
+        self.n_clients = n_clients;
+        self.n_transactions = n_transactions;
+        self
+    }
+
+    /// Choose the [ErrorPolicy] of the [Ledger].
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Build the configured [Ledger].
+    pub fn build(self) -> Ledger {
+        Ledger {
+            accounts: std::collections::HashMap::with_capacity(self.n_clients),
+            transaction_amounts: std::collections::HashMap::with_capacity(self.n_transactions),
+            transaction_state: std::collections::HashMap::with_capacity(self.n_transactions),
+            evicted_transactions: std::collections::HashSet::new(),
+            error_policy: self.error_policy,
+            admin_log: Vec::new(),
+            history: std::collections::HashMap::with_capacity(self.n_clients),
+            next_creation_order: 0,
+            last_extend_errors: 0,
+            observers: Observers::default(),
+        }
+    }
+}
+
+/// The set of clients whose accounts were affected by a transaction, see
+/// [Ledger::process_checked].
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AffectedClients(std::collections::BTreeSet<ClientId>);
+
+impl AffectedClients {
+    /// Whether the given client was affected.
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.0.contains(&client)
+    }
+
+    /// Iterate over all affected clients, in order.
+    pub fn iter(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.0.iter().cloned()
+    }
+
+    /// The number of affected clients.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no clients were affected.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Options used to configure the output of [Ledger::dump_csv_with_options].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CsvDumpOptions {
+    /// Output amounts with a fixed number of places past the decimal, rounding if necessary.
+    /// Amounts are output as they are stored when `None`.
+    pub precision: Option<usize>,
+}
+
+/// A point-in-time copy of a [Ledger]'s state, see [Ledger::snapshot] and [Ledger::restore].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerSnapshot(Ledger);
+
+impl LedgerSnapshot {
+    /// Compare the accounts at the time of the snapshot with those of the given [Ledger], see
+    /// [Ledger::diff].
+    pub fn diff(&self, ledger: &Ledger) -> LedgerDiff {
+        self.0.diff(ledger)
+    }
+}
+
+/// A checkpoint of a [Ledger] saved to a file, see [Ledger::checkpoint]. The file is removed when
+/// the guard is dropped, unless [CheckpointGuard::keep] is called.
+#[derive(Debug)]
+pub struct CheckpointGuard {
+    // Only `None` once the file has been kept
+    path: Option<std::path::PathBuf>,
+}
+
+impl CheckpointGuard {
+    /// The path of the checkpoint file.
+    pub fn path(&self) -> &std::path::Path {
+        self.path
+            .as_deref()
+            .expect("checkpoint path should only be taken when kept")
+    }
+
+    /// Keep the checkpoint file around, returning its path.
+    pub fn keep(mut self) -> std::path::PathBuf {
+        self.path
+            .take()
+            .expect("checkpoint path should only be taken when kept")
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+impl Drop for CheckpointGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            // Nothing useful can be done on failure when dropping
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// The account-level differences between two [Ledger]s, see [Ledger::diff].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LedgerDiff {
+    /// All accounts which changed, appeared, or disappeared, ordered by client.
+    pub changes: Vec<AccountChange>,
+}
+
+impl LedgerDiff {
+    /// Whether both [Ledger]s have the same accounts.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// A change in a client's account between two [Ledger]s, see [Ledger::diff].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AccountChange {
+    pub client: ClientId,
+    /// The account before the change, `None` if it did not exist.
+    pub before: Option<AccountInfo>,
+    /// The account after the change, `None` if it does not exist anymore.
+    pub after: Option<AccountInfo>,
+}
+
+/// Aggregate statistics about a [Ledger], see [Ledger::stats].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LedgerStats {
+    pub num_accounts: usize,
+    /// Each side of a [Transfer] is counted separately.
+    pub num_transactions: usize,
+    pub num_disputes_pending: usize,
+    pub num_locked_accounts: usize,
+    pub total_available: TxAmount,
+    pub total_held: TxAmount,
+}
+
+/// A report on a client's account, see [Ledger::account_statement].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccountStatement {
+    pub client: ClientId,
+    pub available_funds: TxAmount,
+    pub held_funds: TxAmount,
+    pub total_funds: TxAmount,
+    pub locked: bool,
+    /// The number of recorded transactions, each side of a [Transfer] is counted separately.
+    pub transaction_count: usize,
+    /// The number of recorded transactions which have been disputed, including those which were
+    /// later resolved or charged back.
+    pub dispute_count: usize,
+    /// The number of recorded transactions which have been charged back.
+    pub chargeback_count: usize,
+}
+
+/// The balance and status of a client's account. It is (de)serialized using the same field names
+/// as the columns of [Ledger::dump_csv], along with its credit limit and creation order.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "AccountRecord", into = "AccountRecord")]
 pub struct AccountInfo {
     available_funds: TxAmount,
     held_funds: TxAmount,
     locked: bool,
+    credit_limit: TxAmount,
+    creation_order: u64,
+}
+
+// A type used to (de)serialize [AccountInfo], the total funds are ignored when deserializing as
+// they are derived from the other fields.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+struct AccountRecord {
+    available: TxAmount,
+    held: TxAmount,
+    #[serde(default)]
+    total: TxAmount,
+    locked: bool,
+    // Always serialized, as skipping fields is not supported by non self-describing formats
+    #[serde(default)]
+    credit_limit: TxAmount,
+    #[serde(default)]
+    creation_order: u64,
+}
+
+// A type used to (de)serialize an [AccountInfo] along with the [ClientId] it belongs to, using the
+// same fields as the columns of [Ledger::dump_csv]. The total funds are ignored when deserializing
+// as they are derived from the other fields.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+struct ClientAccountRecord {
+    client: ClientId,
+    available: TxAmount,
+    held: TxAmount,
+    total: TxAmount,
+    locked: bool,
+}
+
+impl TryFrom<AccountRecord> for AccountInfo {
+    type Error = LedgerError;
+
+    fn try_from(value: AccountRecord) -> Result<Self, Self::Error> {
+        let AccountRecord {
+            available,
+            held,
+            total: _,
+            locked,
+            credit_limit,
+            creation_order,
+        } = value;
+
+        let mut account = Self {
+            locked,
+            credit_limit,
+            creation_order,
+            ..Default::default()
+        };
+        account.set_funds(available, held)?;
+        Ok(account)
+    }
+}
+
+impl From<AccountInfo> for AccountRecord {
+    fn from(value: AccountInfo) -> Self {
+        Self {
+            available: value.available_funds(),
+            held: value.held_funds(),
+            total: value.total_funds(),
+            locked: value.is_locked(),
+            credit_limit: value.credit_limit(),
+            creation_order: value.creation_order(),
+        }
+    }
 }
 
 /// Represent the state of a transaction. Here are the possible transitions:
@@ -29,7 +368,7 @@ pub struct AccountInfo {
 /// ```
 ///
 /// The starting state is `Processed`.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum TxState {
     /// A transaction was just accepted.
     Processed,
@@ -42,12 +381,27 @@ pub enum TxState {
 }
 
 impl TxState {
+    /// Whether no further transitions are possible from this state.
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Resolved | Self::ChargedBack)
+    }
+
+    /// Whether the transition from this state to the `next` one is valid.
+    pub fn can_transition_to(self, next: TxState) -> bool {
+        matches!(
+            (self, next),
+            (Self::Processed, Self::Disputed)
+                | (Self::Disputed, Self::Resolved)
+                | (Self::Disputed, Self::ChargedBack)
+        )
+    }
+
     pub fn apply_dispute(
         &mut self,
         account: &mut AccountInfo,
         amount: TxAmount,
     ) -> LedgerResult<()> {
-        if *self != Self::Processed {
+        if !self.can_transition_to(Self::Disputed) {
             return Err(LedgerError::AlreadyDisputed);
         }
 
@@ -61,7 +415,7 @@ impl TxState {
         account: &mut AccountInfo,
         amount: TxAmount,
     ) -> LedgerResult<()> {
-        if *self != Self::Disputed {
+        if !self.can_transition_to(Self::Resolved) {
             return Err(LedgerError::NotDisputed);
         }
 
@@ -75,7 +429,7 @@ impl TxState {
         account: &mut AccountInfo,
         amount: TxAmount,
     ) -> LedgerResult<()> {
-        if *self != Self::Disputed {
+        if !self.can_transition_to(Self::ChargedBack) {
             return Err(LedgerError::NotDisputed);
         }
 
@@ -85,6 +439,32 @@ impl TxState {
     }
 }
 
+impl std::fmt::Display for TxState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Processed => "processed",
+            Self::Disputed => "disputed",
+            Self::Resolved => "resolved",
+            Self::ChargedBack => "charged_back",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for TxState {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "processed" => Ok(Self::Processed),
+            "disputed" => Ok(Self::Disputed),
+            "resolved" => Ok(Self::Resolved),
+            "charged_back" => Ok(Self::ChargedBack),
+            _ => Err(ParseError::UnknownTxState(s.into())),
+        }
+    }
+}
+
 type LedgerResult<T> = Result<T, LedgerError>;
 
 impl Ledger {
@@ -92,417 +472,4293 @@ impl Ledger {
         Default::default()
     }
 
+    /// Create an empty [Ledger], pre-allocating space for the given number of accounts and
+    /// transactions.
+    pub fn with_capacity(n_accounts: usize, n_transactions: usize) -> Self {
+        Self::builder()
+            .with_capacity(n_accounts, n_transactions)
+            .build()
+    }
+
+    /// Create a [LedgerBuilder] to configure a new [Ledger].
+    pub fn builder() -> LedgerBuilder {
+        LedgerBuilder::new()
+    }
+
+    /// The [ErrorPolicy] used by this [Ledger].
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
     /// Serialize a [Ledger] to CSV.
     pub fn dump_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
-        // Keep list of accounts ordered for easier diffs
-        let ordered_accounts: std::collections::BTreeMap<_, _> = self.accounts.iter().collect();
-        writer.write_record(&["client", "available", "held", "total", "locked"])?;
-        for (id, info) in ordered_accounts.into_iter() {
+        self.dump_csv_with_options(writer, CsvDumpOptions::default())
+    }
+
+    /// Serialize a [Ledger] to CSV, configuring the output with [CsvDumpOptions].
+    pub fn dump_csv_with_options<W: std::io::Write>(
+        &self,
+        writer: &mut csv::Writer<W>,
+        options: CsvDumpOptions,
+    ) -> csv::Result<()> {
+        let format_amount = |amount: TxAmount| match options.precision {
+            Some(precision) => format!("{:.*}", precision, amount),
+            None => amount.to_string(),
+        };
+        writer.write_record(["client", "available", "held", "total", "locked"])?;
+        for (id, info) in self.ordered_accounts() {
             writer.write_record(&[
                 id.0.to_string(),
-                info.available_funds().0.to_string(),
-                info.held_funds().0.to_string(),
-                info.total_funds().0.to_string(),
+                format_amount(info.available_funds()),
+                format_amount(info.held_funds()),
+                format_amount(info.total_funds()),
                 info.is_locked().to_string(),
             ])?
         }
         Ok(())
     }
 
-    pub fn process(&mut self, tx: Transaction) -> LedgerResult<()> {
-        match tx {
-            Transaction::Deposit(Deposit { client, tx, amount }) => self.delta(client, tx, amount),
-            Transaction::Withdrawal(Withdrawal { client, tx, amount }) => {
-                self.delta(client, tx, -amount)
+    /// Deserialize a [Ledger] from CSV, as written by [Ledger::dump_csv]. Accounts are created in
+    /// the order they appear in the input.
+    ///
+    /// Only the accounts' balances and status can be recovered this way: the resulting [Ledger]
+    /// has no transaction history, meaning that no previous transaction can be disputed, and the
+    /// accounts have no credit limit. Use [Ledger::load_json] to restore the full state instead.
+    /// Accounts with negative held funds are rejected, as they could not pass
+    /// [Ledger::is_consistent] without the disputed debits in their history.
+    pub fn from_csv_reader<R: std::io::Read>(reader: R) -> csv::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut ledger = Self::new();
+        for record in reader.deserialize() {
+            let ClientAccountRecord {
+                client,
+                available,
+                held,
+                total: _,
+                locked,
+            } = record?;
+            let mut account = AccountInfo {
+                locked,
+                ..Default::default()
+            };
+            let invalid_data = |err: String| {
+                csv::Error::from(std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+            };
+            account
+                .set_funds(available, held)
+                .map_err(|err| invalid_data(err.to_string()))?;
+            if held.is_negative() {
+                return Err(invalid_data(format!(
+                    "account of user '{}' cannot be restored without its history",
+                    client
+                )));
             }
-            Transaction::Dispute(tx) => self.dispute(tx),
-            Transaction::Resolve(tx) => self.resolve(tx),
-            Transaction::Chargeback(tx) => self.chargeback(tx),
+            ledger.insert_account(client, account);
         }
+        Ok(ledger)
     }
 
-    fn delta(&mut self, client: ClientId, tx: TxId, delta: TxAmount) -> LedgerResult<()> {
-        let account = self.accounts.entry(client).or_default();
-        account.apply_delta(delta)?;
-        self.transaction_amounts.insert((client, tx), delta);
-        self.transaction_state
-            .insert((client, tx), TxState::Processed);
+    /// Serialize a [Ledger] to TSV, using the same columns as [Ledger::dump_csv] separated by
+    /// tabulations.
+    pub fn dump_tsv<W: std::io::Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_writer(writer);
+        self.dump_csv(&mut writer)?;
+        writer.flush()?;
         Ok(())
     }
 
-    fn dispute(&mut self, Dispute { client, tx }: Dispute) -> LedgerResult<()> {
-        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
-        current_state.apply_dispute(account, amount)
+    /// Serialize a [Ledger] to a CSV formatted [String], see [Ledger::dump_csv].
+    pub fn dump_csv_to_string(&self) -> csv::Result<String> {
+        let mut bytes = vec![];
+        let mut writer = csv::Writer::from_writer(&mut bytes);
+        self.dump_csv(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        Ok(String::from_utf8(bytes).expect("CSV output should be valid UTF-8"))
     }
 
-    fn resolve(&mut self, Resolve { client, tx }: Resolve) -> LedgerResult<()> {
-        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
-        current_state.apply_resolution(account, amount)
+    /// Serialize a [Ledger] to a CSV file, see [Ledger::dump_csv].
+    pub fn dump_csv_to_path(&self, path: impl AsRef<std::path::Path>) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        self.dump_csv(&mut writer)?;
+        writer.flush()?;
+        Ok(())
     }
 
-    fn chargeback(&mut self, Chargeback { client, tx }: Chargeback) -> LedgerResult<()> {
-        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
-        current_state.apply_chargeback(account, amount)
+    /// Take a snapshot of the current state of the [Ledger], which can later be restored.
+    pub fn snapshot(&self) -> LedgerSnapshot {
+        LedgerSnapshot(self.clone())
     }
 
-    fn get_past_transaction_info(
-        &mut self,
-        client: ClientId,
-        tx: TxId,
-    ) -> LedgerResult<(&mut TxState, &mut AccountInfo, TxAmount)> {
-        let current_state = self
-            .transaction_state
-            .get_mut(&(client, tx))
-            .ok_or(LedgerError::UnknownTx(client, tx))?;
-        let account = self
+    /// Restore the [Ledger] to the state it was in when the [LedgerSnapshot] was taken.
+    pub fn restore(&mut self, snap: LedgerSnapshot) {
+        // Observers are not part of the snapshot, keep them subscribed
+        let observers = std::mem::take(&mut self.observers);
+        *self = snap.0;
+        self.observers = observers;
+    }
+
+    /// Compare the accounts of this [Ledger] with those of `other`, which is considered to be the
+    /// most recent state.
+    pub fn diff(&self, other: &Ledger) -> LedgerDiff {
+        let clients: std::collections::BTreeSet<_> = self
             .accounts
-            .get_mut(&client)
-            .expect("a processed transaction should have its account recorded");
-        let amount = self
-            .transaction_amounts
-            .get(&(client, tx))
+            .keys()
+            .chain(other.accounts.keys())
             .cloned()
-            .expect("a processed transaction should have its amount recorded");
-        Ok((current_state, account, amount))
+            .collect();
+        let changes = clients
+            .into_iter()
+            .map(|client| AccountChange {
+                client,
+                before: self.accounts.get(&client).cloned(),
+                after: other.accounts.get(&client).cloned(),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        LedgerDiff { changes }
     }
-}
 
-impl AccountInfo {
-    /// Whether or not an account has been locked.
-    pub fn is_locked(&self) -> bool {
-        self.locked
+    /// Split the [Ledger] into one [Ledger] per client, containing only its account, transactions,
+    /// history, and administrative actions. Merging all of them back together results in the
+    /// same accounts and transactions, see [Ledger::merge].
+    pub fn partition_by_client(&self) -> std::collections::HashMap<ClientId, Ledger> {
+        let mut partitions: std::collections::HashMap<_, _> = self
+            .accounts
+            .iter()
+            .map(|(&client, &info)| {
+                let mut ledger = Ledger::builder().error_policy(self.error_policy).build();
+                ledger.accounts.insert(client, info);
+                ledger.next_creation_order = self.next_creation_order;
+                if let Some(history) = self.history.get(&client) {
+                    ledger.history.insert(client, history.clone());
+                }
+                (client, ledger)
+            })
+            .collect();
+        for (&(client, tx), &amount) in self.transaction_amounts.iter() {
+            if let Some(ledger) = partitions.get_mut(&client) {
+                ledger.transaction_amounts.insert((client, tx), amount);
+                if let Some(&state) = self.transaction_state.get(&(client, tx)) {
+                    ledger.transaction_state.insert((client, tx), state);
+                }
+            }
+        }
+        for &(client, tx) in self.evicted_transactions.iter() {
+            if let Some(ledger) = partitions.get_mut(&client) {
+                ledger.evicted_transactions.insert((client, tx));
+            }
+        }
+        for action in self.admin_log.iter() {
+            let AdminAction::Unlock { client, .. } = action;
+            if let Some(ledger) = partitions.get_mut(client) {
+                ledger.admin_log.push(action.clone());
+            }
+        }
+        partitions
     }
 
-    /// The funds that are usable on this account.
-    pub fn available_funds(&self) -> TxAmount {
-        self.available_funds
+    /// Process a batch of transactions, in parallel for transactions of different clients,
+    /// returning the index in `txs` of each failed transaction along with its error. All
+    /// transactions are attempted, regardless of the [ErrorPolicy].
+    ///
+    /// The [Ledger] is split once with [Ledger::partition_by_client], each client's transactions
+    /// are processed in order on its own partition, and the partitions are then merged back with
+    /// [Ledger::merge]. The batch is split at each [Transfer], which touches two partitions and is
+    /// processed on its own. New accounts are ordered as if the batch had been processed
+    /// sequentially. Observers are notified of each transaction in the order of `txs`, once the
+    /// whole batch has been processed.
+    #[cfg(feature = "rayon")]
+    pub fn process_parallel(&mut self, txs: Vec<Transaction>) -> Vec<(usize, LedgerError)> {
+        let mut partitions = self.partition_by_client();
+        // Where each new account was created in the batch: the index of the first successful
+        // transaction involving it, and whether it was the destination of a transfer
+        let mut created = std::collections::HashMap::new();
+
+        let mut results = Vec::with_capacity(txs.len());
+        let mut segment = Vec::new();
+        for (index, tx) in txs.into_iter().enumerate() {
+            if let Transaction::Transfer(transfer) = tx {
+                results.extend(self.process_segment(
+                    &mut partitions,
+                    &mut created,
+                    std::mem::take(&mut segment),
+                ));
+                let result = self.process_partitioned_transfer(
+                    &mut partitions,
+                    &mut created,
+                    index,
+                    transfer,
+                );
+                results.push((index, tx, result));
+            } else {
+                segment.push((index, tx));
+            }
+        }
+        results.extend(self.process_segment(&mut partitions, &mut created, segment));
+
+        self.merge_partitions(partitions, created);
+        let mut errors = Vec::new();
+        for (index, tx, result) in results {
+            self.observers.notify(&tx, &result);
+            if let Err(err) = result {
+                errors.push((index, err));
+            }
+        }
+        errors
     }
 
-    /// The funds that have been locked pending resolution of dispute.
-    pub fn held_funds(&self) -> TxAmount {
-        self.held_funds
+    // Process transactions which only touch a single account each, in parallel across clients,
+    // returning their results in order
+    #[cfg(feature = "rayon")]
+    fn process_segment(
+        &self,
+        partitions: &mut std::collections::HashMap<ClientId, Ledger>,
+        created: &mut std::collections::HashMap<ClientId, (usize, bool)>,
+        txs: Vec<(usize, Transaction)>,
+    ) -> Vec<(usize, Transaction, LedgerResult<()>)> {
+        use rayon::prelude::*;
+
+        let mut groups: std::collections::HashMap<ClientId, Vec<_>> = Default::default();
+        for (index, tx) in txs.into_iter() {
+            groups.entry(tx.client_id()).or_default().push((index, tx));
+        }
+        let work: Vec<_> = groups
+            .into_iter()
+            .map(|(client, txs)| (client, self.take_partition(partitions, client), txs))
+            .collect();
+        let processed: Vec<_> = work
+            .into_par_iter()
+            .map(|(client, mut ledger, txs)| {
+                let mut creation = None;
+                let results: Vec<_> = txs
+                    .into_iter()
+                    .map(|(index, tx)| {
+                        let result = ledger.dispatch(tx);
+                        if creation.is_none() && self.is_created(&ledger, client) {
+                            creation = Some((index, false));
+                        }
+                        (index, tx, result)
+                    })
+                    .collect();
+                (client, ledger, creation, results)
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for (client, ledger, creation, partition_results) in processed {
+            partitions.insert(client, ledger);
+            if let Some(creation) = creation {
+                created.entry(client).or_insert(creation);
+            }
+            results.extend(partition_results);
+        }
+        results.sort_by_key(|(index, _, _)| *index);
+        results
     }
 
-    /// The totals funds on an account, i.e: available funds and held funds.
-    pub fn total_funds(&self) -> TxAmount {
-        self.available_funds + self.held_funds
+    // Process a transfer between the partitions of both of its clients
+    #[cfg(feature = "rayon")]
+    fn process_partitioned_transfer(
+        &self,
+        partitions: &mut std::collections::HashMap<ClientId, Ledger>,
+        created: &mut std::collections::HashMap<ClientId, (usize, bool)>,
+        index: usize,
+        transfer: Transfer,
+    ) -> LedgerResult<()> {
+        let Transfer { from, to, .. } = transfer;
+        let mut source = self.take_partition(partitions, from);
+        if from == to {
+            // A self-transfer only has one partition, and fails like it would sequentially
+            let result = source.dispatch(Transaction::Transfer(transfer));
+            partitions.insert(from, source);
+            return result;
+        }
+        let mut destination = self.take_partition(partitions, to);
+        let result = source.transfer_to(&mut destination, transfer);
+        for (client, ledger, is_destination) in [(from, &source, false), (to, &destination, true)] {
+            if self.is_created(ledger, client) {
+                created.entry(client).or_insert((index, is_destination));
+            }
+        }
+        partitions.insert(from, source);
+        partitions.insert(to, destination);
+        result
     }
 
-    pub fn apply_delta(&mut self, delta: TxAmount) -> LedgerResult<()> {
-        self.check_frozen()?;
-        let new_balance = self.available_funds() + delta;
-        if new_balance < TxAmount::ZERO {
-            return Err(LedgerError::NotEnoughFunds);
+    // Take the partition of a client out of `partitions`, or create it for a new client
+    #[cfg(feature = "rayon")]
+    fn take_partition(
+        &self,
+        partitions: &mut std::collections::HashMap<ClientId, Ledger>,
+        client: ClientId,
+    ) -> Ledger {
+        partitions
+            .remove(&client)
+            .unwrap_or_else(|| Ledger::builder().error_policy(self.error_policy).build())
+    }
+
+    // Whether the partition has created an account which did not exist in this ledger
+    #[cfg(feature = "rayon")]
+    fn is_created(&self, partition: &Ledger, client: ClientId) -> bool {
+        !self.accounts.contains_key(&client) && partition.accounts.contains_key(&client)
+    }
+
+    // Merge back the partitions of `process_parallel`, existing accounts keep their
+    // `creation_order` while new ones are ordered by their creation in the batch
+    #[cfg(feature = "rayon")]
+    fn merge_partitions(
+        &mut self,
+        partitions: std::collections::HashMap<ClientId, Ledger>,
+        created: std::collections::HashMap<ClientId, (usize, bool)>,
+    ) {
+        let mut existing = Vec::new();
+        let mut new = Vec::new();
+        for (client, ledger) in partitions {
+            match (self.accounts.get(&client), created.get(&client)) {
+                (Some(info), _) => existing.push((info.creation_order, ledger)),
+                (None, Some(&creation)) => new.push((creation, ledger)),
+                // All transactions of the client failed, leaving nothing to merge
+                (None, None) => {}
+            }
         }
-        self.available_funds = new_balance;
-        Ok(())
+        new.sort_by_key(|(creation, _)| *creation);
+        let new = new
+            .into_iter()
+            .zip(self.next_creation_order..)
+            .map(|((_, ledger), creation_order)| (creation_order, ledger));
+
+        let mut merged = Ledger::builder().error_policy(self.error_policy).build();
+        for (creation_order, ledger) in existing.into_iter().chain(new) {
+            // Merging inserts new accounts with the next `creation_order`
+            merged.next_creation_order = creation_order;
+            merged
+                .merge(ledger)
+                .expect("partitions should not share any transaction");
+        }
+        merged.next_creation_order = self.next_creation_order + created.len() as u64;
+        // Records of clients without an account are not part of any partition
+        merged
+            .evicted_transactions
+            .extend(self.evicted_transactions.drain());
+        merged.admin_log = std::mem::take(&mut self.admin_log);
+        merged.last_extend_errors = self.last_extend_errors;
+        merged.observers = std::mem::take(&mut self.observers);
+        *self = merged;
     }
 
-    pub fn apply_dispute(&mut self, delta: TxAmount) -> LedgerResult<()> {
-        self.check_frozen()?;
-        // FIXME: should we check for negative funds?
-        self.available_funds -= delta;
-        self.held_funds += delta;
+    /// Merge another [Ledger] into this one. Balances of accounts present in both are summed.
+    /// Transactions present in both ledgers must have the same amount and state, in which case
+    /// they are only accounted for once, otherwise [LedgerError::ConflictingTx] is returned and
+    /// the [Ledger] is left untouched. A transaction evicted from one ledger, see
+    /// [Ledger::evict_finalized_transactions], must be final in the other one, and stays evicted
+    /// once merged. As its amount is forgotten, it cannot have been evicted from both of them.
+    /// Similarly, summed balances which would overflow result in [LedgerError::Overflow].
+    pub fn merge(&mut self, other: Ledger) -> LedgerResult<()> {
+        // Pair each transaction with its state, checking for conflicts along the way
+        let mut transactions = Vec::with_capacity(other.transaction_amounts.len());
+        // Transactions accounted for in both ledgers, with their amount and state
+        let mut duplicates = Vec::new();
+        for (&key, &amount) in other.transaction_amounts.iter() {
+            let state = *other
+                .transaction_state
+                .get(&key)
+                .ok_or(LedgerError::InconsistentTx(key.0, key.1))?;
+            if let Some(&existing) = self.transaction_amounts.get(&key) {
+                if existing != amount || self.transaction_state.get(&key) != Some(&state) {
+                    return Err(LedgerError::ConflictingTx(key.0, key.1));
+                }
+                duplicates.push((key, amount, state));
+            } else if self.evicted_transactions.contains(&key) {
+                if !state.is_final() {
+                    return Err(LedgerError::ConflictingTx(key.0, key.1));
+                }
+                duplicates.push((key, amount, state));
+            }
+            transactions.push((key, amount, state));
+        }
+        for &key in other.evicted_transactions.iter() {
+            if self.evicted_transactions.contains(&key) {
+                return Err(LedgerError::ConflictingTx(key.0, key.1));
+            }
+            if let Some(&amount) = self.transaction_amounts.get(&key) {
+                let state = *self
+                    .transaction_state
+                    .get(&key)
+                    .ok_or(LedgerError::InconsistentTx(key.0, key.1))?;
+                if !state.is_final() {
+                    return Err(LedgerError::ConflictingTx(key.0, key.1));
+                }
+                duplicates.push((key, amount, state));
+            }
+        }
+
+        // Compute the merged accounts up-front, to leave the ledger untouched on overflow
+        let mut merged = std::collections::HashMap::new();
+        for (&client, &info) in other.accounts.iter() {
+            if let Some(&account) = self.accounts.get(&client) {
+                let account = merged.entry(client).or_insert(account);
+                account.merge(info)?;
+            }
+        }
+        for &(key, amount, state) in duplicates.iter() {
+            // The transaction was accounted for in both ledgers, only keep one of them
+            merged
+                .get_mut(&key.0)
+                .ok_or(LedgerError::InconsistentTx(key.0, key.1))?
+                .remove_duplicate(state, amount)?;
+        }
+
+        for (client, &info) in other.ordered_accounts() {
+            match merged.remove(&client) {
+                Some(account) => self.insert_account(client, account),
+                None => self.insert_account(client, info),
+            }
+        }
+        for (key, amount, state) in transactions {
+            if self.evicted_transactions.contains(&key) {
+                continue;
+            }
+            if let std::collections::hash_map::Entry::Vacant(entry) =
+                self.transaction_amounts.entry(key)
+            {
+                entry.insert(amount);
+                self.transaction_state.insert(key, state);
+            }
+        }
+        for key in other.evicted_transactions {
+            self.transaction_amounts.remove(&key);
+            self.transaction_state.remove(&key);
+            self.evicted_transactions.insert(key);
+        }
+        self.admin_log.extend(other.admin_log);
+        for (client, transactions) in other.history.into_iter() {
+            let history = self.history.entry(client).or_default();
+            // Shared transactions should only appear once
+            let key = |tx: &Transaction| (std::mem::discriminant(tx), tx.client_id(), tx.tx_id());
+            let mut seen: std::collections::HashSet<_> = history.iter().map(key).collect();
+            for tx in transactions {
+                if seen.insert(key(&tx)) {
+                    history.push(tx);
+                }
+            }
+        }
         Ok(())
     }
 
-    pub fn apply_resolution(&mut self, delta: TxAmount) -> LedgerResult<()> {
-        self.check_frozen()?;
-        // FIXME: should we check for negative funds?
-        self.available_funds += delta;
-        self.held_funds -= delta;
+    /// Unfreeze a locked account, recording the reason for doing so in the administrative log.
+    pub fn admin_unlock(&mut self, client: ClientId, reason: &str) -> LedgerResult<()> {
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .ok_or(LedgerError::UnknownClient(client))?;
+        account.locked = false;
+        self.admin_log.push(AdminAction::Unlock {
+            client,
+            reason: reason.into(),
+        });
         Ok(())
     }
 
-    pub fn apply_chargeback(&mut self, delta: TxAmount) -> LedgerResult<()> {
-        self.check_frozen()?;
-        // FIXME: should we check for negative funds?
-        self.held_funds -= delta;
-        self.locked = true;
+    /// Allow a client's available funds to go negative, down to `-limit`.
+    pub fn set_credit_limit(&mut self, client: ClientId, limit: TxAmount) -> LedgerResult<()> {
+        if limit.is_negative() {
+            return Err(LedgerError::NegativeCreditLimit);
+        }
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .ok_or(LedgerError::UnknownClient(client))?;
+        account.credit_limit = limit.round_to_4dp();
         Ok(())
     }
 
-    fn check_frozen(&self) -> LedgerResult<()> {
-        if self.is_locked() {
-            Err(LedgerError::FrozenAccount)
-        } else {
-            Ok(())
-        }
+    /// The amount a client can still overdraw from their account, if it exists, see
+    /// [Ledger::set_credit_limit].
+    pub fn available_credit(&self, client: ClientId) -> Option<TxAmount> {
+        self.accounts
+            .get(&client)
+            .map(AccountInfo::available_credit)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use expect_test::{expect, Expect};
+    /// All administrative actions taken on this [Ledger], in order.
+    pub fn admin_log(&self) -> &[AdminAction] {
+        &self.admin_log
+    }
 
-    macro_rules! inline_csv {
-        ($line:literal) => {
-            $line
+    /// Forget about all transactions in a final [TxState], which cannot be disputed anymore,
+    /// returning the number of transactions evicted. Any subsequent reference to those
+    /// transactions results in [LedgerError::UnknownTx] instead of [LedgerError::AlreadyDisputed].
+    /// Their ids are still remembered, so that re-using them is rejected as
+    /// [LedgerError::DuplicateTx].
+    pub fn evict_finalized_transactions(&mut self) -> usize {
+        let finalized: Vec<_> = self
+            .transaction_state
+            .iter()
+            .filter(|(_, state)| state.is_final())
+            .map(|(key, _)| *key)
+            .collect();
+        for key in finalized.iter() {
+            self.transaction_state.remove(key);
+            self.transaction_amounts.remove(key);
+            self.evicted_transactions.insert(*key);
+        }
+        finalized.len()
+    }
+
+    /// Remove all frozen accounts from the [Ledger], along with their transaction records and
+    /// history, returning them.
+    pub fn drain_locked_accounts(&mut self) -> std::collections::HashMap<ClientId, AccountInfo> {
+        let locked: std::collections::HashMap<_, _> = self
+            .accounts
+            .iter()
+            .filter(|(_, info)| info.is_locked())
+            .map(|(&client, &info)| (client, info))
+            .collect();
+        self.accounts
+            .retain(|client, _| !locked.contains_key(client));
+        self.transaction_amounts
+            .retain(|(client, _), _| !locked.contains_key(client));
+        self.transaction_state
+            .retain(|(client, _), _| !locked.contains_key(client));
+        self.evicted_transactions
+            .retain(|(client, _)| !locked.contains_key(client));
+        self.history
+            .retain(|client, _| !locked.contains_key(client));
+        locked
+    }
+
+    /// Resolve all currently disputed transactions, ordered by client and transaction id,
+    /// returning the result of each resolution.
+    pub fn resolve_all_pending_disputes(&mut self) -> Vec<LedgerResult<()>> {
+        self.sorted_pending_disputes()
+            .into_iter()
+            .map(|(client, tx)| self.process(Transaction::new_resolve(client, tx)))
+            .collect()
+    }
+
+    /// Charge back all currently disputed transactions, ordered by client and transaction id,
+    /// returning the result of each chargeback.
+    pub fn chargeback_all_pending_disputes(&mut self) -> Vec<LedgerResult<()>> {
+        self.sorted_pending_disputes()
+            .into_iter()
+            .map(|(client, tx)| self.process(Transaction::new_chargeback(client, tx)))
+            .collect()
+    }
+
+    /// Re-assign the account of client `old` and all its records to `new_id`, including the
+    /// references made by other clients' transfers in their history.
+    pub fn rename_client(&mut self, old: ClientId, new_id: ClientId) -> LedgerResult<()> {
+        if !self.accounts.contains_key(&old) {
+            return Err(LedgerError::UnknownClient(old));
+        }
+        if self.accounts.contains_key(&new_id) {
+            return Err(LedgerError::ClientAlreadyExists(new_id));
+        }
+        let rename = |client: ClientId| if client == old { new_id } else { client };
+
+        let account = self.accounts.remove(&old).expect("checked above");
+        self.accounts.insert(new_id, account);
+        self.transaction_amounts = std::mem::take(&mut self.transaction_amounts)
+            .into_iter()
+            .map(|((client, tx), amount)| ((rename(client), tx), amount))
+            .collect();
+        self.transaction_state = std::mem::take(&mut self.transaction_state)
+            .into_iter()
+            .map(|((client, tx), state)| ((rename(client), tx), state))
+            .collect();
+        self.evicted_transactions = std::mem::take(&mut self.evicted_transactions)
+            .into_iter()
+            .map(|(client, tx)| (rename(client), tx))
+            .collect();
+        if let Some(history) = self.history.remove(&old) {
+            self.history.insert(new_id, history);
+        }
+        self.rename_in_history(old, new_id);
+        Ok(())
+    }
+
+    /// Consolidate the account of client `secondary` into the one of `primary`: balances are
+    /// summed, all transaction records and history are moved over, and the `secondary` account is
+    /// removed. Transactions present for both clients result in [LedgerError::ConflictingTx], in
+    /// which case the [Ledger] is left untouched.
+    pub fn merge_clients(&mut self, primary: ClientId, secondary: ClientId) -> LedgerResult<()> {
+        if primary == secondary {
+            return Ok(());
+        }
+        let mut account = *self
+            .accounts
+            .get(&primary)
+            .ok_or(LedgerError::UnknownClient(primary))?;
+        let other = *self
+            .accounts
+            .get(&secondary)
+            .ok_or(LedgerError::UnknownClient(secondary))?;
+        account.check_frozen()?;
+        other.check_frozen()?;
+        let moved: Vec<_> = self
+            .transaction_amounts
+            .keys()
+            .chain(self.evicted_transactions.iter())
+            .filter(|(client, _)| *client == secondary)
+            .map(|&(_, tx)| tx)
+            .collect();
+        if let Some(&tx) = moved
+            .iter()
+            .find(|&&tx| self.check_duplicate(primary, tx).is_err())
+        {
+            return Err(LedgerError::ConflictingTx(primary, tx));
+        }
+
+        account.merge(other)?;
+        self.accounts.insert(primary, account);
+        self.accounts.remove(&secondary);
+        for tx in moved {
+            if let Some(amount) = self.transaction_amounts.remove(&(secondary, tx)) {
+                self.transaction_amounts.insert((primary, tx), amount);
+            }
+            if let Some(state) = self.transaction_state.remove(&(secondary, tx)) {
+                self.transaction_state.insert((primary, tx), state);
+            }
+            if self.evicted_transactions.remove(&(secondary, tx)) {
+                self.evicted_transactions.insert((primary, tx));
+            }
+        }
+        if let Some(history) = self.history.remove(&secondary) {
+            self.history.entry(primary).or_default().extend(history);
+        }
+        self.rename_in_history(secondary, primary);
+        Ok(())
+    }
+
+    /// Release any memory over-allocated by [Ledger::with_capacity] or by processing a large batch
+    /// of transactions.
+    pub fn shrink_to_fit(&mut self) {
+        self.accounts.shrink_to_fit();
+        self.transaction_amounts.shrink_to_fit();
+        self.transaction_state.shrink_to_fit();
+        self.evicted_transactions.shrink_to_fit();
+        self.admin_log.shrink_to_fit();
+        self.history.shrink_to_fit();
+        self.history.values_mut().for_each(Vec::shrink_to_fit);
+    }
+
+    /// Check the internal invariants of the [Ledger], returning all broken ones. This can be used
+    /// to audit a [Ledger] after operations which bypass [Ledger::process], such as
+    /// [Ledger::merge] or [Ledger::restore]. The invariants are:
+    ///
+    /// * every recorded transaction has an account, an amount, and a state.
+    /// * the held funds of an account are at least the sum of its disputed transactions. In
+    ///   particular they are non-negative, unless a debit is being disputed.
+    pub fn is_consistent(&self) -> Result<(), Vec<InconsistencyError>> {
+        let mut errors = Vec::new();
+        let mut disputed_funds = std::collections::HashMap::<ClientId, TxAmount>::new();
+        for (&(client, tx), &amount) in self.transaction_amounts.iter() {
+            if !self.accounts.contains_key(&client) {
+                errors.push(InconsistencyError::MissingAccount(client, tx));
+            }
+            match self.transaction_state.get(&(client, tx)) {
+                None => errors.push(InconsistencyError::MissingState(client, tx)),
+                Some(TxState::Disputed) => *disputed_funds.entry(client).or_default() += amount,
+                Some(_) => {}
+            }
+        }
+        for &(client, tx) in self.transaction_state.keys() {
+            if !self.transaction_amounts.contains_key(&(client, tx)) {
+                errors.push(InconsistencyError::MissingAmount(client, tx));
+            }
+        }
+        for (&client, info) in self.accounts.iter() {
+            let expected = disputed_funds.get(&client).cloned().unwrap_or_default();
+            // Held funds can exceed the disputes, e.g: when loaded by `from_csv_reader`
+            if info.held_funds() < expected {
+                errors.push(InconsistencyError::HeldFundsTooLow {
+                    client,
+                    held: info.held_funds(),
+                    expected,
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            // Sort the errors to report them deterministically
+            errors.sort();
+            Err(errors)
+        }
+    }
+
+    /// Serialize the full state of a [Ledger] to JSON, which can be read back with
+    /// [Ledger::load_json].
+    pub fn dump_json<W: std::io::Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+
+    /// Serialize the accounts of a [Ledger] to JSON, as an array of objects using the same field
+    /// names as the columns of [Ledger::dump_csv], sorted by [ClientId].
+    pub fn dump_json_accounts<W: std::io::Write>(&self, writer: W) -> serde_json::Result<()> {
+        let mut accounts: Vec<_> = self.iter_accounts().collect();
+        accounts.sort_by_key(|(id, _)| *id);
+        let records: Vec<_> = accounts
+            .into_iter()
+            .map(|(client, info)| ClientAccountRecord {
+                client,
+                available: info.available_funds(),
+                held: info.held_funds(),
+                total: info.total_funds(),
+                locked: info.is_locked(),
+            })
+            .collect();
+        serde_json::to_writer(writer, &records)
+    }
+
+    /// Deserialize a [Ledger] from JSON, as written by [Ledger::dump_json]. The loaded state is
+    /// rejected if it is not consistent, see [Ledger::is_consistent].
+    pub fn load_json<R: std::io::Read>(reader: R) -> serde_json::Result<Self> {
+        serde_json::from_reader::<_, Self>(reader)?.check_loaded()
+    }
+
+    /// Save the full state of a [Ledger] to a JSON file, see [Ledger::dump_json].
+    ///
+    /// The state is first written to a temporary file next to the destination, which is then
+    /// renamed over it, so that an interrupted save never leaves a corrupted file behind.
+    pub fn save_to_path(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), LedgerPersistenceError> {
+        let path = path.as_ref();
+        let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let write = || -> Result<(), LedgerPersistenceError> {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+            self.dump_json(&mut writer)?;
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, path)?;
+            Ok(())
         };
-        ($line:literal, $($lines:literal),+ $(,)?) => {
-            concat!($line, "\n", inline_csv!($($lines),+))
+        let result = write();
+        if result.is_err() {
+            // Best effort clean-up, the original error is more relevant
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    /// Load the full state of a [Ledger] from a JSON file, as written by [Ledger::save_to_path].
+    pub fn load_from_path(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, LedgerPersistenceError> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(Self::load_json(reader)?)
+    }
+
+    /// Save the full state of a [Ledger] to a new file in the given directory, see
+    /// [Ledger::save_to_path]. The file is removed once the returned [CheckpointGuard] is dropped.
+    pub fn checkpoint(
+        &self,
+        dir: &std::path::Path,
+    ) -> Result<CheckpointGuard, LedgerPersistenceError> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = dir.join(format!(
+            "ledger-checkpoint-{}-{}.json",
+            std::process::id(),
+            id
+        ));
+        self.save_to_path(&path)?;
+        Ok(CheckpointGuard { path: Some(path) })
+    }
+
+    /// Load a [Ledger] from a checkpoint, see [Ledger::checkpoint]. The guard is consumed, meaning
+    /// that the checkpoint file is removed afterwards, even if loading it failed. A checkpoint
+    /// file kept with [CheckpointGuard::keep] can be loaded with [Ledger::load_from_path].
+    pub fn restore_checkpoint(guard: CheckpointGuard) -> Result<Self, LedgerPersistenceError> {
+        Self::load_from_path(guard.path())
+    }
+
+    /// Serialize the full state of a [Ledger] to a compact binary format, which can be read back
+    /// with [Ledger::load_bincode].
+    #[cfg(feature = "bincode")]
+    pub fn dump_bincode<W: std::io::Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self)
+    }
+
+    /// Deserialize a [Ledger] from the binary format written by [Ledger::dump_bincode]. Like
+    /// [Ledger::load_json], the loaded state is rejected if it is not consistent.
+    #[cfg(feature = "bincode")]
+    pub fn load_bincode<R: std::io::Read>(reader: R) -> bincode::Result<Self> {
+        bincode::deserialize_from::<_, Self>(reader)?.check_loaded()
+    }
+
+    // Deserialized state did not go through `process`, make sure that it upholds its invariants
+    fn check_loaded<E: serde::de::Error>(self) -> Result<Self, E> {
+        match self.is_consistent() {
+            Ok(()) => Ok(self),
+            Err(errors) => {
+                let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+                Err(E::custom(format!(
+                    "inconsistent ledger: {}",
+                    errors.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Get the [AccountInfo] of a given client, if it has been part of a processed transaction.
+    pub fn get_account(&self, client: ClientId) -> Option<&AccountInfo> {
+        self.accounts.get(&client)
+    }
+
+    /// Iterate over all accounts in the ledger. The iteration order is unspecified.
+    pub fn iter_accounts(&self) -> impl Iterator<Item = (ClientId, &AccountInfo)> {
+        self.accounts.iter().map(|(id, info)| (*id, info))
+    }
+
+    /// Iterate over the [ClientId] of all accounts in the ledger. The iteration order is
+    /// unspecified.
+    pub fn known_clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.accounts.keys().cloned()
+    }
+
+    /// Compute a report on the account of a given client, if it has been part of a processed
+    /// transaction.
+    pub fn account_statement(&self, client: ClientId) -> Option<AccountStatement> {
+        let account = self.accounts.get(&client)?;
+        let mut statement = AccountStatement {
+            client,
+            available_funds: account.available_funds(),
+            held_funds: account.held_funds(),
+            total_funds: account.total_funds(),
+            locked: account.is_locked(),
+            transaction_count: 0,
+            dispute_count: 0,
+            chargeback_count: 0,
         };
+        let states = self
+            .transaction_state
+            .iter()
+            .filter(|((id, _), _)| *id == client)
+            .map(|(_, state)| *state);
+        for state in states {
+            statement.transaction_count += 1;
+            if state != TxState::Processed {
+                statement.dispute_count += 1;
+            }
+            if state == TxState::ChargedBack {
+                statement.chargeback_count += 1;
+            }
+        }
+        Some(statement)
     }
 
-    fn process_transactions(input: &str) -> Result<Ledger, LedgerError> {
+    /// The sum of all funds ever credited to a given client's account, by deposits and incoming
+    /// transfers, regardless of later disputes. Unknown clients have not been credited anything,
+    /// and get [TxAmount::ZERO]. Saturates at [TxAmount::MAX] if the sum is too large to be
+    /// represented.
+    pub fn total_deposited_for_client(&self, client: ClientId) -> TxAmount {
+        TxAmount::saturating_sum(
+            self.client_amounts(client)
+                .filter(|amount| amount.is_positive()),
+        )
+    }
+
+    /// The sum of all funds ever debited from a given client's account, by withdrawals and
+    /// outgoing transfers, regardless of later disputes. Unknown clients have not been debited
+    /// anything, and get [TxAmount::ZERO]. Saturates at [TxAmount::MAX] if the sum is too large to
+    /// be represented.
+    pub fn total_withdrawn_for_client(&self, client: ClientId) -> TxAmount {
+        TxAmount::saturating_sum(
+            self.client_amounts(client)
+                .filter(|amount| amount.is_negative())
+                .map(TxAmount::abs),
+        )
+    }
+
+    /// The highest [TxId] recorded in the ledger across all clients, if any. This includes the
+    /// transactions forgotten by [Ledger::evict_finalized_transactions], as their ids cannot be
+    /// re-used.
+    pub fn max_transaction_id(&self) -> Option<TxId> {
+        self.transaction_amounts
+            .keys()
+            .chain(self.evicted_transactions.iter())
+            .map(|&(_, tx)| tx)
+            .max()
+    }
+
+    /// Whether the ledger has an account for the given client.
+    pub fn has_client(&self, client: ClientId) -> bool {
+        self.accounts.contains_key(&client)
+    }
+
+    /// Whether the ledger has a record of the given transaction. Both sides of a [Transfer] are
+    /// recorded under the same [TxId] for their respective clients.
+    pub fn has_transaction(&self, client: ClientId, tx: TxId) -> bool {
+        self.transaction_amounts.contains_key(&(client, tx))
+    }
+
+    /// The number of accounts in the ledger.
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// The number of transactions recorded in the ledger, each side of a [Transfer] is counted
+    /// separately.
+    pub fn transaction_count(&self) -> usize {
+        self.transaction_amounts.len()
+    }
+
+    /// The sum of the available funds of all accounts. Saturates at [TxAmount::MIN] or
+    /// [TxAmount::MAX] if it is too large to be represented, see [TxAmount::saturating_sum].
+    pub fn total_available_funds(&self) -> TxAmount {
+        TxAmount::saturating_sum(self.iter_accounts().map(|(_, info)| info.available_funds()))
+    }
+
+    /// The sum of the held funds of all accounts. Saturates at [TxAmount::MIN] or
+    /// [TxAmount::MAX] if it is too large to be represented, see [TxAmount::saturating_sum].
+    pub fn total_held_funds(&self) -> TxAmount {
+        TxAmount::saturating_sum(self.iter_accounts().map(|(_, info)| info.held_funds()))
+    }
+
+    /// The sum of all funds ever credited to all accounts, see
+    /// [Ledger::total_deposited_for_client]. Saturates at [TxAmount::MAX] if it is too large to be
+    /// represented.
+    pub fn total_deposited(&self) -> TxAmount {
+        TxAmount::saturating_sum(
+            self.transaction_amounts
+                .values()
+                .copied()
+                .filter(|amount| amount.is_positive()),
+        )
+    }
+
+    /// The sum of all funds ever debited from all accounts, see
+    /// [Ledger::total_withdrawn_for_client]. Saturates at [TxAmount::MAX] if it is too large to be
+    /// represented.
+    pub fn total_withdrawn(&self) -> TxAmount {
+        TxAmount::saturating_sum(
+            self.transaction_amounts
+                .values()
+                .filter(|amount| amount.is_negative())
+                .map(|amount| amount.abs()),
+        )
+    }
+
+    /// The net sum of all charged back transactions, in which a charged back debit counts
+    /// negatively as its funds were returned to the account. The funds of all accounts add up to
+    /// `total_deposited - total_withdrawn - total_chargedback`. Saturates at [TxAmount::MIN] or
+    /// [TxAmount::MAX] if it is too large to be represented, see [TxAmount::saturating_sum].
+    pub fn total_chargedback(&self) -> TxAmount {
+        TxAmount::saturating_sum(
+            self.transaction_state
+                .iter()
+                .filter(|(_, state)| **state == TxState::ChargedBack)
+                .filter_map(|(key, _)| self.transaction_amounts.get(key).copied()),
+        )
+    }
+
+    /// Iterate over all accounts which hold funds, see [AccountInfo::is_active]. The iteration
+    /// order is unspecified.
+    pub fn active_accounts(&self) -> impl Iterator<Item = (ClientId, &AccountInfo)> {
+        self.iter_accounts().filter(|(_, info)| info.is_active())
+    }
+
+    /// Iterate over all accounts which do not hold any funds, see [AccountInfo::is_active]. The
+    /// iteration order is unspecified.
+    pub fn inactive_accounts(&self) -> impl Iterator<Item = (ClientId, &AccountInfo)> {
+        self.iter_accounts().filter(|(_, info)| !info.is_active())
+    }
+
+    /// All accounts satisfying the given predicate, in the order they were created.
+    pub fn filter_accounts<F: Fn(ClientId, &AccountInfo) -> bool>(
+        &self,
+        predicate: F,
+    ) -> Vec<(ClientId, &AccountInfo)> {
+        let mut accounts = self.ordered_accounts();
+        accounts.retain(|(id, info)| predicate(*id, info));
+        accounts
+    }
+
+    /// Iterate over all clients whose account is frozen. The iteration order is unspecified.
+    pub fn locked_accounts(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.iter_accounts()
+            .filter(|(_, info)| info.is_locked())
+            .map(|(id, _)| id)
+    }
+
+    /// The number of frozen accounts.
+    pub fn num_locked_accounts(&self) -> usize {
+        self.locked_accounts().count()
+    }
+
+    /// Export all recorded transactions along with their signed amount and current [TxState],
+    /// ordered by client and transaction id. Each side of a [Transfer] is exported separately.
+    pub fn export_transactions(&self) -> Vec<(ClientId, TxId, TxAmount, TxState)> {
+        let mut transactions: Vec<_> = self
+            .transaction_amounts
+            .iter()
+            .map(|(&(client, tx), &amount)| {
+                let state = self
+                    .transaction_state
+                    .get(&(client, tx))
+                    .cloned()
+                    .expect("a processed transaction should have its state recorded");
+                (client, tx, amount, state)
+            })
+            .collect();
+        transactions.sort_by_key(|&(client, tx, _, _)| (client, tx));
+        transactions
+    }
+
+    /// Compute all aggregate statistics about the ledger at once, which is cheaper than calling
+    /// each individual method. The total funds saturate at [TxAmount::MIN] or [TxAmount::MAX] if
+    /// they are too large to be represented, see [TxAmount::saturating_sum].
+    pub fn stats(&self) -> LedgerStats {
+        let accounts = self.accounts.values();
+        LedgerStats {
+            num_accounts: self.accounts.len(),
+            num_transactions: self.transaction_amounts.len(),
+            num_disputes_pending: self
+                .transaction_state
+                .values()
+                .filter(|state| **state == TxState::Disputed)
+                .count(),
+            num_locked_accounts: accounts.clone().filter(|info| info.is_locked()).count(),
+            total_available: TxAmount::saturating_sum(
+                accounts.clone().map(AccountInfo::available_funds),
+            ),
+            total_held: TxAmount::saturating_sum(accounts.map(AccountInfo::held_funds)),
+        }
+    }
+
+    /// All successfully processed transactions affecting a given client, in processing order.
+    pub fn transaction_history(&self, client: ClientId) -> Option<&[Transaction]> {
+        self.history.get(&client).map(Vec::as_slice)
+    }
+
+    /// Get the current [TxState] of a given transaction, if it has been processed.
+    pub fn get_transaction_state(&self, client: ClientId, tx: TxId) -> Option<TxState> {
+        self.transaction_state.get(&(client, tx)).cloned()
+    }
+
+    /// Iterate over all transactions which are currently disputed. The iteration order is
+    /// unspecified.
+    pub fn pending_disputes(&self) -> impl Iterator<Item = (ClientId, TxId)> + '_ {
+        self.transaction_state
+            .iter()
+            .filter(|(_, state)| **state == TxState::Disputed)
+            .map(|(key, _)| *key)
+    }
+
+    /// Get the amount recorded for a given transaction, if it has been processed. This is the
+    /// signed delta applied to the account, i.e: withdrawals are negative.
+    pub fn get_transaction_amount(&self, client: ClientId, tx: TxId) -> Option<TxAmount> {
+        self.transaction_amounts.get(&(client, tx)).cloned()
+    }
+
+    pub fn process(&mut self, tx: Transaction) -> LedgerResult<()> {
+        let result = self.dispatch(tx);
+        self.observers.notify(&tx, &result);
+        result
+    }
+
+    /// Register a [LedgerObserver] to be called synchronously for each processed transaction,
+    /// returning an [ObserverId] to [Ledger::unsubscribe] it later. Observers are not carried over
+    /// when cloning the [Ledger].
+    pub fn subscribe(&mut self, observer: Box<dyn LedgerObserver>) -> ObserverId {
+        let id = ObserverId(self.observers.next_id);
+        self.observers.next_id += 1;
+        self.observers.observers.push((id, observer));
+        id
+    }
+
+    /// Remove a [LedgerObserver] registered with [Ledger::subscribe], returning it if it was
+    /// still subscribed.
+    pub fn unsubscribe(&mut self, id: ObserverId) -> Option<Box<dyn LedgerObserver>> {
+        let index = self
+            .observers
+            .observers
+            .iter()
+            .position(|(observer_id, _)| *observer_id == id)?;
+        Some(self.observers.observers.remove(index).1)
+    }
+
+    fn dispatch(&mut self, tx: Transaction) -> LedgerResult<()> {
+        match tx {
+            Transaction::Deposit(Deposit { client, tx, amount }) => {
+                self.delta(client, tx, positive_amount(amount)?)
+            }
+            Transaction::Withdrawal(Withdrawal { client, tx, amount }) => {
+                self.delta(client, tx, -positive_amount(amount)?)
+            }
+            Transaction::Dispute(tx) => self.dispute(tx),
+            Transaction::Resolve(tx) => self.resolve(tx),
+            Transaction::Chargeback(tx) => self.chargeback(tx),
+            Transaction::Transfer(tx) => self.transfer(tx),
+        }?;
+        self.record_history(tx);
+        Ok(())
+    }
+
+    /// The number of transactions which failed during the last call to [Ledger::extend].
+    pub fn last_extend_errors(&self) -> usize {
+        self.last_extend_errors
+    }
+
+    /// Process a transaction given as raw fields, as they would appear in a CSV record, without
+    /// going through a CSV reader. Transfers are not supported, as they need a destination.
+    pub fn process_transaction_fields(
+        &mut self,
+        type_: &str,
+        client: u16,
+        tx: u32,
+        amount: Option<&str>,
+    ) -> Result<(), ProcessError> {
+        let amount = amount.map(str::parse).transpose()?;
+        let tx = Transaction::from_fields(type_, ClientId(client), TxId(tx), amount)?;
+        Ok(self.process(tx)?)
+    }
+
+    /// Process a transaction, returning the set of clients whose accounts were affected by it.
+    pub fn process_checked(&mut self, tx: Transaction) -> LedgerResult<AffectedClients> {
+        self.process(tx)?;
+        let mut affected = std::collections::BTreeSet::new();
+        affected.insert(tx.client_id());
+        if let Transaction::Transfer(Transfer { to, .. }) = tx {
+            affected.insert(to);
+        }
+        Ok(AffectedClients(affected))
+    }
+
+    /// Process a transaction, restoring the previous state of the [Ledger] if it fails. This
+    /// guarantees that no partial changes are kept, at the cost of copying the whole [Ledger] for
+    /// each transaction. Observers are notified once the [Ledger] has been restored.
+    pub fn process_or_rollback(&mut self, tx: Transaction) -> LedgerResult<()> {
+        let snapshot = self.snapshot();
+        let result = self.dispatch(tx);
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+        self.observers.notify(&tx, &result);
+        result
+    }
+
+    /// Transfer funds between two accounts, see [Transfer]. Either both accounts are updated, or
+    /// the [Ledger] is left untouched.
+    pub fn process_transfer(
+        &mut self,
+        from: ClientId,
+        to: ClientId,
+        tx: TxId,
+        amount: TxAmount,
+    ) -> LedgerResult<()> {
+        self.process_or_rollback(Transaction::new_transfer(from, to, tx, amount))
+    }
+
+    /// Process a transaction, ignoring it if it results in an error, which is returned for logging
+    /// purposes. A failed transaction leaves the [Ledger] untouched.
+    pub fn process_soft(&mut self, tx: Transaction) -> Option<LedgerError> {
+        self.process(tx).err()
+    }
+
+    /// Process a batch of transactions in order, returning the result of each one. With the
+    /// [ErrorPolicy::Soft] policy, an error in one transaction does not stop the processing of the
+    /// following ones. With [ErrorPolicy::Strict], processing stops after the first error.
+    pub fn process_batch(
+        &mut self,
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) -> Vec<LedgerResult<()>> {
+        let mut results = Vec::new();
+        for tx in transactions {
+            let res = self.process(tx);
+            let failed = res.is_err();
+            results.push(res);
+            if failed && self.error_policy == ErrorPolicy::Strict {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Process transactions in order until they are exhausted or the timeout expires, returning
+    /// the number of processed transactions. Failed transactions are ignored, see
+    /// [Ledger::process_soft], but count as processed. The timeout is checked before each
+    /// transaction, so the [Ledger] always reflects all transactions that were processed.
+    pub fn process_with_timeout(
+        &mut self,
+        txs: impl IntoIterator<Item = Transaction>,
+        timeout: std::time::Duration,
+    ) -> Result<usize, TimeoutError> {
+        // A timeout too large to be represented never expires
+        let deadline = std::time::Instant::now().checked_add(timeout);
+        let mut processed = 0;
+        for tx in txs {
+            if deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline) {
+                return Err(TimeoutError { processed });
+            }
+            self.process_soft(tx);
+            processed += 1;
+        }
+        Ok(processed)
+    }
+
+    /// Rebuild a [Ledger] from scratch by replaying all transactions in order. Failed transactions
+    /// do not stop the replay, they are returned along with their 1-based index in the input.
+    pub fn replay_events(
+        events: impl IntoIterator<Item = Transaction>,
+    ) -> (Ledger, Vec<(usize, LedgerError)>) {
         let mut ledger = Ledger::new();
-        for tx in Transaction::configured_csv_reader_builder()
-            .from_reader(input.as_bytes())
-            .into_deserialize()
-        {
-            ledger.process(tx.unwrap())?
+        let errors = events
+            .into_iter()
+            .zip(1..)
+            .filter_map(|(tx, index)| ledger.process_soft(tx).map(|err| (index, err)))
+            .collect();
+        (ledger, errors)
+    }
+
+    /// Process all transactions from a CSV stream, as read by
+    /// [Transaction::configured_csv_reader_builder]. Failed transactions are handled according to
+    /// the [ErrorPolicy], and returned along with their 1-based index in the input. Malformed
+    /// input, including wrong headers, stops processing and is returned as an error.
+    pub fn process_csv_reader<R: std::io::Read>(
+        &mut self,
+        reader: R,
+    ) -> csv::Result<Vec<(usize, LedgerError)>> {
+        let mut reader = Transaction::configured_csv_reader_builder().from_reader(reader);
+        Transaction::validate_csv_headers(&mut reader)?;
+        let mut errors = Vec::new();
+        for (tx, index) in Transaction::read_csv(&mut reader)?.zip(1..) {
+            if let Err(err) = self.process(tx?) {
+                errors.push((index, err));
+                if self.error_policy == ErrorPolicy::Strict {
+                    break;
+                }
+            }
         }
-        Ok(ledger)
+        Ok(errors)
     }
 
-    fn check_ledger(ledger: &Ledger, expect: Expect) {
-        let mut writer = csv::Writer::from_writer(vec![]);
-        ledger.dump_csv(&mut writer).unwrap();
-        let actual = String::from_utf8(writer.into_inner().unwrap()).unwrap();
-        expect.assert_eq(&actual);
+    /// Process all transactions from a CSV file, see [Ledger::process_csv_reader].
+    pub fn process_csv_path(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> csv::Result<Vec<(usize, LedgerError)>> {
+        let file = std::fs::File::open(path)?;
+        self.process_csv_reader(file)
+    }
+
+    fn delta(&mut self, client: ClientId, tx: TxId, delta: TxAmount) -> LedgerResult<()> {
+        self.check_duplicate(client, tx)?;
+        // Make sure the recorded amount matches the one applied to the account
+        let delta = delta.round_to_4dp();
+        // Only record the account once the transaction has succeeded
+        let mut account = self.accounts.get(&client).cloned().unwrap_or_default();
+        account.apply_delta(delta)?;
+        self.insert_account(client, account);
+        self.record_transaction(client, tx, delta);
+        Ok(())
+    }
+
+    fn transfer(&mut self, transfer: Transfer) -> LedgerResult<()> {
+        let (amount, debited, credited) = self.check_transfer(self, transfer)?;
+        let Transfer { from, to, tx, .. } = transfer;
+        self.insert_account(from, debited);
+        self.insert_account(to, credited);
+        self.record_transaction(from, tx, -amount);
+        self.record_transaction(to, tx, amount);
+        Ok(())
+    }
+
+    // Check that a transfer from an account of this ledger to one of `destination` can go
+    // through, returning the transferred amount along with both updated accounts
+    fn check_transfer(
+        &self,
+        destination: &Ledger,
+        Transfer {
+            from,
+            to,
+            tx,
+            amount,
+        }: Transfer,
+    ) -> LedgerResult<(TxAmount, AccountInfo, AccountInfo)> {
+        if from == to {
+            return Err(LedgerError::SelfTransfer);
+        }
+        self.check_duplicate(from, tx)?;
+        destination.check_duplicate(to, tx)?;
+        let amount = positive_amount(amount)?;
+        // Only record the accounts once both sides of the transfer have succeeded
+        let mut debited = self.accounts.get(&from).cloned().unwrap_or_default();
+        debited.apply_delta(-amount)?;
+        let mut credited = destination.accounts.get(&to).cloned().unwrap_or_default();
+        credited.apply_delta(amount)?;
+        Ok((amount, debited, credited))
+    }
+
+    // Transfer funds from an account of this ledger to one of `destination`, recording the
+    // transfer in both of them
+    #[cfg(feature = "rayon")]
+    fn transfer_to(&mut self, destination: &mut Ledger, transfer: Transfer) -> LedgerResult<()> {
+        let (amount, debited, credited) = self.check_transfer(destination, transfer)?;
+        let Transfer { from, to, tx, .. } = transfer;
+        self.insert_account(from, debited);
+        self.record_transaction(from, tx, -amount);
+        self.history
+            .entry(from)
+            .or_default()
+            .push(Transaction::Transfer(transfer));
+        destination.insert_account(to, credited);
+        destination.record_transaction(to, tx, amount);
+        destination
+            .history
+            .entry(to)
+            .or_default()
+            .push(Transaction::Transfer(transfer));
+        Ok(())
+    }
+
+    fn record_history(&mut self, tx: Transaction) {
+        self.history.entry(tx.client_id()).or_default().push(tx);
+        // Transfers affect two accounts, they should appear in both histories
+        if let Transaction::Transfer(Transfer { to, .. }) = tx {
+            self.history.entry(to).or_default().push(tx);
+        }
+    }
+
+    // Replace all references to `old` in the transaction history by `new_id`
+    fn rename_in_history(&mut self, old: ClientId, new_id: ClientId) {
+        let rename = |client: ClientId| if client == old { new_id } else { client };
+        for tx in self.history.values_mut().flatten() {
+            *tx = match *tx {
+                Transaction::Deposit(Deposit { client, tx, amount }) => {
+                    Transaction::new_deposit(rename(client), tx, amount)
+                }
+                Transaction::Withdrawal(Withdrawal { client, tx, amount }) => {
+                    Transaction::new_withdrawal(rename(client), tx, amount)
+                }
+                Transaction::Dispute(Dispute { client, tx }) => {
+                    Transaction::new_dispute(rename(client), tx)
+                }
+                Transaction::Resolve(Resolve { client, tx }) => {
+                    Transaction::new_resolve(rename(client), tx)
+                }
+                Transaction::Chargeback(Chargeback { client, tx }) => {
+                    Transaction::new_chargeback(rename(client), tx)
+                }
+                Transaction::Transfer(Transfer {
+                    from,
+                    to,
+                    tx,
+                    amount,
+                }) => Transaction::new_transfer(rename(from), rename(to), tx, amount),
+            };
+        }
+    }
+
+    fn sorted_pending_disputes(&self) -> Vec<(ClientId, TxId)> {
+        let mut disputes: Vec<_> = self.pending_disputes().collect();
+        disputes.sort();
+        disputes
+    }
+
+    // The recorded deltas of all transactions of a given client
+    fn client_amounts(&self, client: ClientId) -> impl Iterator<Item = TxAmount> + '_ {
+        self.transaction_amounts
+            .iter()
+            .filter(move |((id, _), _)| *id == client)
+            .map(|(_, amount)| *amount)
+    }
+
+    // Accounts are ordered by creation, for deterministic and easier to debug outputs
+    fn ordered_accounts(&self) -> Vec<(ClientId, &AccountInfo)> {
+        let mut accounts: Vec<_> = self.iter_accounts().collect();
+        accounts.sort_by_key(|(_, info)| info.creation_order());
+        accounts
+    }
+
+    // Insert or update an account, assigning its `creation_order` if it is new
+    fn insert_account(&mut self, client: ClientId, mut account: AccountInfo) {
+        match self.accounts.entry(client) {
+            std::collections::hash_map::Entry::Occupied(mut entry) => {
+                entry.insert(account);
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                account.creation_order = self.next_creation_order;
+                self.next_creation_order += 1;
+                entry.insert(account);
+            }
+        }
+    }
+
+    fn check_duplicate(&self, client: ClientId, tx: TxId) -> LedgerResult<()> {
+        let key = (client, tx);
+        if self.transaction_amounts.contains_key(&key) || self.evicted_transactions.contains(&key) {
+            return Err(LedgerError::DuplicateTx(client, tx));
+        }
+        Ok(())
+    }
+
+    fn record_transaction(&mut self, client: ClientId, tx: TxId, delta: TxAmount) {
+        self.transaction_amounts.insert((client, tx), delta);
+        self.transaction_state
+            .insert((client, tx), TxState::Processed);
+    }
+
+    fn dispute(&mut self, Dispute { client, tx }: Dispute) -> LedgerResult<()> {
+        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
+        current_state.apply_dispute(account, amount)
+    }
+
+    fn resolve(&mut self, Resolve { client, tx }: Resolve) -> LedgerResult<()> {
+        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
+        current_state.apply_resolution(account, amount)
+    }
+
+    fn chargeback(&mut self, Chargeback { client, tx }: Chargeback) -> LedgerResult<()> {
+        let (current_state, account, amount) = self.get_past_transaction_info(client, tx)?;
+        current_state.apply_chargeback(account, amount)
+    }
+
+    fn get_past_transaction_info(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+    ) -> LedgerResult<(&mut TxState, &mut AccountInfo, TxAmount)> {
+        if !self.accounts.contains_key(&client) {
+            return Err(LedgerError::UnknownClient(client));
+        }
+        let current_state = self
+            .transaction_state
+            .get_mut(&(client, tx))
+            .ok_or(LedgerError::UnknownTx(client, tx))?;
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .expect("a processed transaction should have its account recorded");
+        let amount = self
+            .transaction_amounts
+            .get(&(client, tx))
+            .cloned()
+            .expect("a processed transaction should have its amount recorded");
+        Ok((current_state, account, amount))
+    }
+}
+
+/// Process all transactions in order, ignoring failed ones. The number of failures can be
+/// retrieved using [Ledger::last_extend_errors].
+impl Extend<Transaction> for Ledger {
+    fn extend<T: IntoIterator<Item = Transaction>>(&mut self, iter: T) {
+        self.last_extend_errors = iter
+            .into_iter()
+            .filter_map(|tx| self.process_soft(tx))
+            .count();
+    }
+}
+
+/// Process all transactions in order into a new [Ledger], stopping at the first error.
+impl FromIterator<Transaction> for Result<Ledger, LedgerError> {
+    fn from_iter<T: IntoIterator<Item = Transaction>>(iter: T) -> Self {
+        let mut ledger = Ledger::new();
+        iter.into_iter().try_for_each(|tx| ledger.process(tx))?;
+        Ok(ledger)
+    }
+}
+
+/// A single-line summary of the account, amounts are formatted with the given precision if any.
+impl std::fmt::Display for AccountInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (available, held, total) = (
+            self.available_funds(),
+            self.held_funds(),
+            self.total_funds(),
+        );
+        match f.precision() {
+            Some(prec) => write!(
+                f,
+                "available: {available:.prec$}, held: {held:.prec$}, total: {total:.prec$}, locked: {}",
+                self.is_locked()
+            ),
+            None => write!(
+                f,
+                "available: {available}, held: {held}, total: {total}, locked: {}",
+                self.is_locked()
+            ),
+        }
+    }
+}
+
+impl AccountInfo {
+    /// Whether or not an account has been locked.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// The funds that are usable on this account.
+    pub fn available_funds(&self) -> TxAmount {
+        self.available_funds
+    }
+
+    /// The funds that have been locked pending resolution of dispute.
+    pub fn held_funds(&self) -> TxAmount {
+        self.held_funds
+    }
+
+    /// The totals funds on an account, i.e: available funds and held funds. All operations on an
+    /// account keep it within the [TxAmount::MIN] to [TxAmount::MAX] range, failing with
+    /// [LedgerError::Overflow] otherwise, so computing it cannot overflow.
+    pub fn total_funds(&self) -> TxAmount {
+        self.available_funds + self.held_funds
+    }
+
+    /// Whether the account holds any funds, i.e: its total funds are strictly positive.
+    pub fn is_active(&self) -> bool {
+        self.total_funds() > TxAmount::ZERO
+    }
+
+    /// The order in which the account was created in its [Ledger], used to order its outputs.
+    pub fn creation_order(&self) -> u64 {
+        self.creation_order
+    }
+
+    /// How far below zero the available funds are allowed to go, see [Ledger::set_credit_limit].
+    pub fn credit_limit(&self) -> TxAmount {
+        self.credit_limit
+    }
+
+    /// The part of the credit limit which has not been used yet.
+    pub fn available_credit(&self) -> TxAmount {
+        if self.available_funds.is_negative() {
+            self.credit_limit + self.available_funds
+        } else {
+            self.credit_limit
+        }
+    }
+
+    /// The share of the total funds which are available, or `1.0` if the account is empty. A
+    /// ratio close to `0.0` means that most funds are held due to disputes.
+    pub fn net_available_ratio(&self) -> f64 {
+        let total = self.total_funds();
+        if total.is_zero() {
+            return 1.0;
+        }
+        self.available_funds.to_f64() / total.to_f64()
+    }
+
+    /// The share of the total funds which are held, or `0.0` if the account is empty.
+    pub fn held_ratio(&self) -> f64 {
+        let total = self.total_funds();
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.held_funds.to_f64() / total.to_f64()
+    }
+
+    /// Check whether withdrawing the given amount would succeed, without modifying the account.
+    pub fn check_withdrawal_allowed(&self, amount: TxAmount) -> LedgerResult<()> {
+        let mut account = *self;
+        account.apply_delta(-amount)
+    }
+
+    pub fn apply_delta(&mut self, delta: TxAmount) -> LedgerResult<()> {
+        self.check_frozen()?;
+        let delta = delta.round_to_4dp();
+        let new_balance = checked_add(self.available_funds, delta)?;
+        if new_balance < -self.credit_limit {
+            return Err(LedgerError::NotEnoughFunds);
+        }
+        self.set_funds(new_balance, self.held_funds)
+    }
+
+    pub fn apply_dispute(&mut self, delta: TxAmount) -> LedgerResult<()> {
+        self.check_frozen()?;
+        // FIXME: should we check for negative funds?
+        self.set_funds(
+            checked_add(self.available_funds, -delta)?,
+            checked_add(self.held_funds, delta)?,
+        )
+    }
+
+    pub fn apply_resolution(&mut self, delta: TxAmount) -> LedgerResult<()> {
+        self.check_frozen()?;
+        // FIXME: should we check for negative funds?
+        self.set_funds(
+            checked_add(self.available_funds, delta)?,
+            checked_add(self.held_funds, -delta)?,
+        )
+    }
+
+    pub fn apply_chargeback(&mut self, delta: TxAmount) -> LedgerResult<()> {
+        self.check_frozen()?;
+        // FIXME: should we check for negative funds?
+        self.set_funds(self.available_funds, checked_add(self.held_funds, -delta)?)?;
+        self.locked = true;
+        Ok(())
+    }
+
+    fn merge(&mut self, other: AccountInfo) -> LedgerResult<()> {
+        self.set_funds(
+            checked_add(self.available_funds, other.available_funds)?,
+            checked_add(self.held_funds, other.held_funds)?,
+        )?;
+        self.locked |= other.locked;
+        Ok(())
+    }
+
+    // Undo the effect that a transaction in the given state had on the balance
+    fn remove_duplicate(&mut self, state: TxState, amount: TxAmount) -> LedgerResult<()> {
+        match state {
+            TxState::Processed | TxState::Resolved => {
+                self.set_funds(checked_add(self.available_funds, -amount)?, self.held_funds)
+            }
+            TxState::Disputed => {
+                self.set_funds(self.available_funds, checked_add(self.held_funds, -amount)?)
+            }
+            TxState::ChargedBack => Ok(()),
+        }
+    }
+
+    // Update the funds, keeping the total funds within the range of [TxAmount]
+    fn set_funds(&mut self, available: TxAmount, held: TxAmount) -> LedgerResult<()> {
+        checked_add(available, held)?;
+        self.available_funds = available;
+        self.held_funds = held;
+        Ok(())
+    }
+
+    fn check_frozen(&self) -> LedgerResult<()> {
+        if self.is_locked() {
+            Err(LedgerError::FrozenAccount)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn checked_add(lhs: TxAmount, rhs: TxAmount) -> LedgerResult<TxAmount> {
+    lhs.checked_add(rhs).ok_or(LedgerError::Overflow)
+}
+
+// Transactions can be built directly, without the checks done when parsing them. Make sure the
+// recorded amount matches the one applied to the accounts by rounding it.
+fn positive_amount(amount: TxAmount) -> LedgerResult<TxAmount> {
+    let amount = amount.round_to_4dp();
+    if !amount.is_positive() {
+        return Err(LedgerError::NonPositiveAmount);
+    }
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use expect_test::{expect, Expect};
+    use fpdec::{Dec, Decimal};
+
+    macro_rules! inline_csv {
+        ($line:literal) => {
+            $line
+        };
+        ($line:literal, $($lines:literal),+ $(,)?) => {
+            concat!($line, "\n", inline_csv!($($lines),+))
+        };
+    }
+
+    fn process_transactions(input: &str) -> Result<Ledger, LedgerError> {
+        let mut ledger = Ledger::new();
+        for tx in Transaction::configured_csv_reader_builder()
+            .from_reader(input.as_bytes())
+            .into_deserialize()
+        {
+            ledger.process(tx.unwrap())?
+        }
+        Ok(ledger)
+    }
+
+    fn check_ledger(ledger: &Ledger, expect: Expect) {
+        let actual = ledger.dump_csv_to_string().unwrap();
+        expect.assert_eq(&actual);
+    }
+
+    #[test]
+    fn tx_state_transitions() {
+        use TxState::*;
+        let states = [Processed, Disputed, Resolved, ChargedBack];
+        let mut transitions = vec![];
+        for from in states {
+            for to in states {
+                if from.can_transition_to(to) {
+                    transitions.push((from, to));
+                }
+            }
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                (Processed, Disputed),
+                (Disputed, Resolved),
+                (Disputed, ChargedBack)
+            ]
+        );
+        let finals: Vec<_> = states.into_iter().filter(|s| s.is_final()).collect();
+        assert_eq!(finals, vec![Resolved, ChargedBack]);
+    }
+
+    #[test]
+    fn tx_state_display() {
+        use TxState::*;
+        for (state, name) in [
+            (Processed, "processed"),
+            (Disputed, "disputed"),
+            (Resolved, "resolved"),
+            (ChargedBack, "charged_back"),
+        ] {
+            assert_eq!(state.to_string(), name);
+            assert_eq!(name.parse(), Ok(state));
+        }
+        assert_eq!(
+            "ChargedBack".parse::<TxState>(),
+            Err(ParseError::UnknownTxState("ChargedBack".into()))
+        );
+    }
+
+    #[test]
+    fn deposit_single_account() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   2.0",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,3.0,0,3.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deposit_multiple_accounts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "deposit,         1,  3,   2.0",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,3.0,0,3.0,false
+                2,1.0,0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deposit_and_withdrawal() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "deposit,         1,  3,   2.0",
+            "withdrawal,      1,  4,   1.5",
+            "withdrawal,      2,  5,   1.0",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.5,0,1.5,false
+                2,0.0,0,0.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn deposit_and_withdrawal_not_enough_funds() {
+        let error = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         2,  2,   1.0",
+            "withdrawal,      2,  5,   3.0",
+        ))
+        .unwrap_err();
+        assert_eq!(error, LedgerError::NotEnoughFunds);
+    }
+
+    #[test]
+    fn dispute_deposit() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,1.0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn dispute_withdrawal() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "withdrawal,      1,  2,   1.0",
+            "dispute,         1,  2",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,-1.0,0.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn resolve_dispute() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0.0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn chargeback_dispute() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,0.0,0.0,true
+            "#]],
+        );
+    }
+
+    #[test]
+    fn dispute_then_withdrawal() {
+        let error = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "withdrawal,      1,  2,   1.0",
+        ))
+        .unwrap_err();
+        assert_eq!(error, LedgerError::NotEnoughFunds);
+    }
+
+    #[test]
+    fn dispute_then_resolution() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0.0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn dispute_then_chargeback() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,0.0,0.0,true
+            "#]],
+        );
+    }
+
+    #[test]
+    fn dispute_then_resolution_then_withdrawal() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
+            "withdrawal,      1,  2,   1.0",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,0.0,0.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn dispute_then_chargeback_then_withdrawal() {
+        let error = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+            "withdrawal,      1,  2,   1.0",
+        ))
+        .unwrap_err();
+        assert_eq!(error, LedgerError::FrozenAccount);
+    }
+
+    #[test]
+    fn dispute_then_deposit_then_dispute_then_resolve_then_chargeback() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "dispute,         1,  1",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+            "resolve,         1,  2",
+            "chargeback,      1,  1",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+            client,available,held,total,locked
+            1,1.0,0.0,1.0,true
+        "#]],
+        );
+    }
+
+    #[test]
+    fn get_account() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   2.0",
+            "dispute,         1,  1",
+        ))
+        .unwrap();
+        let account = ledger.get_account(ClientId(1)).unwrap();
+        assert_eq!(account.available_funds(), TxAmount(Dec!(2.0)));
+        assert_eq!(account.held_funds(), TxAmount(Dec!(1.0)));
+        assert!(!account.is_locked());
+        assert_eq!(ledger.get_account(ClientId(2)), None);
+    }
+
+    #[test]
+    fn iter_accounts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   2.0",
+        ))
+        .unwrap();
+        let mut accounts: Vec<_> = ledger
+            .iter_accounts()
+            .map(|(id, info)| (id, info.available_funds()))
+            .collect();
+        accounts.sort();
+        assert_eq!(
+            accounts,
+            vec![
+                (ClientId(1), TxAmount(Dec!(1.0))),
+                (ClientId(2), TxAmount(Dec!(2.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn get_transaction_state() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+        ))
+        .unwrap();
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(1), TxId(1)),
+            Some(TxState::Processed)
+        );
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(1), TxId(2)),
+            Some(TxState::Disputed)
+        );
+        assert_eq!(ledger.get_transaction_state(ClientId(2), TxId(1)), None);
+        assert_eq!(ledger.get_transaction_state(ClientId(1), TxId(3)), None);
+    }
+
+    #[test]
+    fn get_transaction_amount() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   2.0",
+            "withdrawal,      1,  2,   1.5",
+        ))
+        .unwrap();
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(1), TxId(1)),
+            Some(TxAmount(Dec!(2.0)))
+        );
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(1), TxId(2)),
+            Some(TxAmount(Dec!(-1.5)))
+        );
+        assert_eq!(ledger.get_transaction_amount(ClientId(2), TxId(1)), None);
+    }
+
+    #[test]
+    fn process_batch() {
+        let mut ledger = Ledger::new();
+        let results = ledger.process_batch(vec![
+            Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: TxAmount(Dec!(1.0)),
+            }),
+            Transaction::Withdrawal(Withdrawal {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(2.0)),
+            }),
+            Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(3),
+                amount: TxAmount(Dec!(2.0)),
+            }),
+        ]);
+        assert_eq!(
+            results,
+            vec![Ok(()), Err(LedgerError::NotEnoughFunds), Ok(())]
+        );
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,3.0,0,3.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let snapshot = ledger.snapshot();
+        ledger
+            .process(Transaction::Dispute(Dispute {
+                client: ClientId(1),
+                tx: TxId(1),
+            }))
+            .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,1.0,1.0,false
+            "#]],
+        );
+        ledger.restore(snapshot);
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0,1.0,false
+            "#]],
+        );
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(1), TxId(1)),
+            Some(TxState::Processed)
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+        ))
+        .unwrap();
+        let other = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  3,   2.0",
+            "deposit,         3,  4,   3.0",
+            "dispute,         3,  4",
+        ))
+        .unwrap();
+        ledger.merge(other).unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,3.0,0,3.0,false
+                2,1.0,0,1.0,false
+                3,0.0,3.0,3.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn merge_conflicting_transactions() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let other = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   2.0",
+        ))
+        .unwrap();
+        let error = ledger.merge(other).unwrap_err();
+        assert_eq!(error, LedgerError::ConflictingTx(ClientId(1), TxId(1)));
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn merge_evicted_transactions() {
+        let resolved = || {
+            process_transactions(inline_csv!(
+                "type,       client, tx, amount",
+                "deposit,         1,  1,   1.0",
+                "dispute,         1,  1",
+                "resolve,         1,  1",
+            ))
+            .unwrap()
+        };
+        let evicted = || {
+            let mut ledger = resolved();
+            assert_eq!(ledger.evict_finalized_transactions(), 1);
+            ledger
+        };
+
+        // Evicted in either ledger, the transaction is only accounted for once
+        for (mut ledger, other) in [(evicted(), resolved()), (resolved(), evicted())] {
+            ledger.merge(other).unwrap();
+            check_ledger(
+                &ledger,
+                expect![[r#"
+                    client,available,held,total,locked
+                    1,1.0,0.0,1.0,false
+                "#]],
+            );
+            assert!(!ledger.has_transaction(ClientId(1), TxId(1)));
+            assert_eq!(ledger.is_consistent(), Ok(()));
+        }
+
+        // A transaction can only be evicted once it is final
+        let mut ledger = evicted();
+        let before = ledger.clone();
+        let other = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        assert_eq!(
+            ledger.merge(other),
+            Err(LedgerError::ConflictingTx(ClientId(1), TxId(1)))
+        );
+        assert_eq!(ledger, before);
+
+        // The amount of a transaction evicted from both ledgers is unknown
+        assert_eq!(
+            ledger.merge(evicted()),
+            Err(LedgerError::ConflictingTx(ClientId(1), TxId(1)))
+        );
+        assert_eq!(ledger, before);
+    }
+
+    #[test]
+    fn process_soft() {
+        let mut ledger = Ledger::new();
+        let error = ledger.process_soft(Transaction::Withdrawal(Withdrawal {
+            client: ClientId(1),
+            tx: TxId(1),
+            amount: TxAmount(Dec!(1.0)),
+        }));
+        assert_eq!(error, Some(LedgerError::NotEnoughFunds));
+        assert_eq!(ledger, Ledger::new());
+        let error = ledger.process_soft(Transaction::Deposit(Deposit {
+            client: ClientId(1),
+            tx: TxId(2),
+            amount: TxAmount(Dec!(1.0)),
+        }));
+        assert_eq!(error, None);
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0,1.0,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn process_batch_strict() {
+        let mut ledger = Ledger::builder().error_policy(ErrorPolicy::Strict).build();
+        let results = ledger.process_batch(vec![
+            Transaction::Withdrawal(Withdrawal {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: TxAmount(Dec!(2.0)),
+            }),
+            Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(2.0)),
+            }),
+        ]);
+        assert_eq!(results, vec![Err(LedgerError::NotEnoughFunds)]);
+        assert_eq!(ledger.get_account(ClientId(1)), None);
+    }
+
+    #[test]
+    fn builder() {
+        let ledger = LedgerBuilder::new().with_capacity(10, 100).build();
+        assert_eq!(ledger, Ledger::new());
+        assert_eq!(ledger.error_policy(), ErrorPolicy::Soft);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   2.0",
+            "withdrawal,      2,  3,   1.5",
+            "dispute,         1,  1",
+            "deposit,         3,  4,   1.0",
+            "dispute,         3,  4",
+            "chargeback,      3,  4",
+        ))
+        .unwrap();
+        let mut json = vec![];
+        ledger.dump_json(&mut json).unwrap();
+        assert_eq!(Ledger::load_json(json.as_slice()).unwrap(), ledger);
+    }
+
+    #[test]
+    fn load_json_inconsistent() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        ledger.transaction_amounts.remove(&(ClientId(1), TxId(1)));
+        let mut json = vec![];
+        ledger.dump_json(&mut json).unwrap();
+        let error = Ledger::load_json(json.as_slice()).unwrap_err();
+        expect![[
+            r#"inconsistent ledger: transaction with user '1', id '1' has no recorded amount"#
+        ]]
+        .assert_eq(&error.to_string());
+    }
+
+    #[test]
+    fn account_info_json() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   2.0",
+            "dispute,         1,  1",
+        ))
+        .unwrap();
+        let account = *ledger.get_account(ClientId(1)).unwrap();
+        let json = serde_json::to_string(&account).unwrap();
+        expect![[
+            r#"{"available":"2.0","held":"1.0","total":"3.0","locked":false,"credit_limit":"0","creation_order":0}"#
+        ]]
+        .assert_eq(&json);
+        assert_eq!(serde_json::from_str::<AccountInfo>(&json).unwrap(), account);
+    }
+
+    #[test]
+    fn deposit_rounding() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.00005",
+            "deposit,         1,  2,   1.00004",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,2.0001,0,2.0001,false
+            "#]],
+        );
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(1), TxId(1)),
+            Some(TxAmount(Dec!(1.0001)))
+        );
+    }
+
+    #[test]
+    fn deposit_overflow() {
+        let mut ledger = Ledger::new();
+        ledger
+            .process(Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: TxAmount::MAX,
+            }))
+            .unwrap();
+        let error = ledger
+            .process(Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(1.0)),
+            }))
+            .unwrap_err();
+        assert_eq!(error, LedgerError::Overflow);
+        assert_eq!(
+            ledger.get_account(ClientId(1)).unwrap().available_funds(),
+            TxAmount::MAX
+        );
+    }
+
+    #[test]
+    fn transfer() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   2.0",
+            "transfer,        1,  2,   1.5,   2",
+        ))
+        .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.5,0,0.5,false
+                2,1.5,0,1.5,false
+            "#]],
+        );
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(1), TxId(2)),
+            Some(TxAmount(Dec!(-1.5)))
+        );
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(2), TxId(2)),
+            Some(TxAmount(Dec!(1.5)))
+        );
+    }
+
+    #[test]
+    fn transfer_non_positive_amount() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         2,  1,  10.0",
+        ))
+        .unwrap();
+        let before = ledger.clone();
+        for (tx, amount) in [(2, Dec!(-5.0)), (3, Dec!(0)), (4, Dec!(0.00001))] {
+            assert_eq!(
+                ledger.process(Transaction::new_transfer(
+                    ClientId(1),
+                    ClientId(2),
+                    TxId(tx),
+                    TxAmount(amount),
+                )),
+                Err(LedgerError::NonPositiveAmount)
+            );
+        }
+        assert_eq!(ledger, before);
+    }
+
+    #[test]
+    fn deposit_and_withdrawal_non_positive_amount() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,  10.0",
+        ))
+        .unwrap();
+        let before = ledger.clone();
+        for (tx, amount) in [(2, Dec!(-5.0)), (3, Dec!(0)), (4, Dec!(0.00001))] {
+            assert_eq!(
+                ledger.process(Transaction::new_deposit(
+                    ClientId(1),
+                    TxId(tx),
+                    TxAmount(amount)
+                )),
+                Err(LedgerError::NonPositiveAmount)
+            );
+            assert_eq!(
+                ledger.process(Transaction::new_withdrawal(
+                    ClientId(1),
+                    TxId(tx + 10),
+                    TxAmount(amount)
+                )),
+                Err(LedgerError::NonPositiveAmount)
+            );
+        }
+        assert_eq!(ledger, before);
+        assert_eq!(
+            ledger.total_deposited_for_client(ClientId(1)),
+            TxAmount(Dec!(10.0))
+        );
+        assert_eq!(
+            ledger.total_withdrawn_for_client(ClientId(1)),
+            TxAmount::ZERO
+        );
+    }
+
+    #[test]
+    fn transfer_not_enough_funds() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let error = ledger
+            .process(Transaction::Transfer(Transfer {
+                from: ClientId(1),
+                to: ClientId(2),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(2.0)),
+            }))
+            .unwrap_err();
+        assert_eq!(error, LedgerError::NotEnoughFunds);
+        assert_eq!(ledger.get_account(ClientId(2)), None);
+        assert_eq!(ledger.get_transaction_state(ClientId(1), TxId(2)), None);
+    }
+
+    #[test]
+    fn transfer_to_frozen_account() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "dispute,         2,  2",
+            "chargeback,      2,  2",
+        ))
+        .unwrap();
+        let error = ledger
+            .process(Transaction::Transfer(Transfer {
+                from: ClientId(1),
+                to: ClientId(2),
+                tx: TxId(3),
+                amount: TxAmount(Dec!(1.0)),
+            }))
+            .unwrap_err();
+        assert_eq!(error, LedgerError::FrozenAccount);
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0,1.0,false
+                2,0.0,0.0,0.0,true
+            "#]],
+        );
+    }
+
+    #[test]
+    fn pending_disputes() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   1.0",
+            "deposit,         2,  3,   1.0",
+            "deposit,         2,  4,   1.0",
+            "dispute,         1,  1",
+            "dispute,         1,  2",
+            "resolve,         1,  2",
+            "dispute,         2,  4",
+        ))
+        .unwrap();
+        let mut disputes: Vec<_> = ledger.pending_disputes().collect();
+        disputes.sort();
+        assert_eq!(
+            disputes,
+            vec![(ClientId(1), TxId(1)), (ClientId(2), TxId(4))]
+        );
+    }
+
+    #[test]
+    fn locked_accounts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "deposit,         3,  3,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+            "dispute,         3,  3",
+            "chargeback,      3,  3",
+        ))
+        .unwrap();
+        let mut locked: Vec<_> = ledger.locked_accounts().collect();
+        locked.sort();
+        assert_eq!(locked, vec![ClientId(1), ClientId(3)]);
+        assert_eq!(ledger.num_locked_accounts(), 2);
+    }
+
+    #[test]
+    fn admin_unlock() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+        ))
+        .unwrap();
+        ledger.admin_unlock(ClientId(1), "fraud cleared").unwrap();
+        ledger
+            .process(Transaction::Withdrawal(Withdrawal {
+                client: ClientId(1),
+                tx: TxId(3),
+                amount: TxAmount(Dec!(1.0)),
+            }))
+            .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.0,0.0,0.0,false
+            "#]],
+        );
+        assert_eq!(
+            ledger.admin_log(),
+            [AdminAction::Unlock {
+                client: ClientId(1),
+                reason: "fraud cleared".into()
+            }]
+        );
+        assert_eq!(
+            ledger.admin_unlock(ClientId(2), "unknown").unwrap_err(),
+            LedgerError::UnknownClient(ClientId(2))
+        );
+    }
+
+    #[test]
+    fn total_funds() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   2.0",
+            "deposit,         3,  3,   3.0",
+            "dispute,         3,  3",
+        ))
+        .unwrap();
+        assert_eq!(ledger.total_available_funds(), TxAmount(Dec!(3.0)));
+        assert_eq!(ledger.total_held_funds(), TxAmount(Dec!(3.0)));
+        assert_eq!(Ledger::new().total_available_funds(), TxAmount::ZERO);
+    }
+
+    // Two accounts with the largest possible balance, whose sums overflow
+    fn max_balances_ledger() -> Ledger {
+        let mut ledger = Ledger::new();
+        for id in 1..=2 {
+            ledger
+                .process(Transaction::new_deposit(
+                    ClientId(id),
+                    TxId(id.into()),
+                    TxAmount::MAX,
+                ))
+                .unwrap();
+        }
+        ledger
+    }
+
+    #[test]
+    fn total_funds_overflow() {
+        let ledger = max_balances_ledger();
+        assert_eq!(ledger.total_available_funds(), TxAmount::MAX);
+        assert_eq!(ledger.total_held_funds(), TxAmount::ZERO);
+    }
+
+    #[test]
+    fn counts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   2.0",
+            "deposit,         1,  3,   3.0",
+            "dispute,         1,  3",
+        ))
+        .unwrap();
+        assert_eq!(ledger.account_count(), 2);
+        assert_eq!(ledger.transaction_count(), 3);
+    }
+
+    #[test]
+    fn process_csv_reader() {
+        let mut ledger = Ledger::new();
+        let errors = ledger
+            .process_csv_reader(
+                inline_csv!(
+                    "type,       client, tx, amount",
+                    "deposit,         1,  1,   1.0",
+                    "withdrawal,      1,  2,   2.0",
+                    "dispute,         1,  3",
+                    "withdrawal,      1,  4,   0.5",
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            errors,
+            vec![
+                (2, LedgerError::NotEnoughFunds),
+                (3, LedgerError::UnknownTx(ClientId(1), TxId(3)))
+            ]
+        );
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.5,0,0.5,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn process_csv_reader_malformed() {
+        let mut ledger = Ledger::new();
+        let res = ledger.process_csv_reader(
+            inline_csv!(
+                "type,       client, tx, amount",
+                "deposit,         1,  1,   1.0",
+                "deposit,         1,  2",
+            )
+            .as_bytes(),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn dump_csv_to_path() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("processor-dump-{}.csv", std::process::id()));
+        ledger.dump_csv_to_path(&path).unwrap();
+        let actual = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(actual, ledger.dump_csv_to_string().unwrap());
+    }
+
+    #[test]
+    fn save_and_load_path() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   2.0",
+            "dispute,         2,  2",
+        ))
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("processor-save-{}.json", std::process::id()));
+        ledger.save_to_path(&path).unwrap();
+        let loaded = Ledger::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), ledger);
+        assert!(matches!(
+            Ledger::load_from_path(&path),
+            Err(LedgerPersistenceError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let dir = std::env::temp_dir();
+
+        let guard = ledger.checkpoint(&dir).unwrap();
+        let path = guard.path().to_owned();
+        let expected = ledger.clone();
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(2),
+                TxId(2),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        assert_eq!(Ledger::restore_checkpoint(guard).unwrap(), expected);
+        assert!(!path.exists());
+
+        let path = ledger.checkpoint(&dir).unwrap().keep();
+        let loaded = Ledger::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), ledger);
+
+        let path = ledger.checkpoint(&dir).unwrap().path().to_owned();
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_round_trip() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   1.0,",
+            "deposit,         2,  2,   2.0,",
+            "transfer,        2,  3,   0.5,  1",
+            "dispute,         2,  2,,",
+            "deposit,         3,  4,   1.0,",
+            "dispute,         3,  4,,",
+            "chargeback,      3,  4,,",
+        ))
+        .unwrap();
+        ledger
+            .set_credit_limit(ClientId(1), TxAmount(Dec!(10)))
+            .unwrap();
+        let mut bytes = Vec::new();
+        ledger.dump_bincode(&mut bytes).unwrap();
+        assert_eq!(Ledger::load_bincode(bytes.as_slice()).unwrap(), ledger);
+    }
+
+    #[test]
+    fn dump_csv_with_precision() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.25",
+            "dispute,         2,  2",
+        ))
+        .unwrap();
+        let mut writer = csv::Writer::from_writer(vec![]);
+        ledger
+            .dump_csv_with_options(&mut writer, CsvDumpOptions { precision: Some(4) })
+            .unwrap();
+        let actual = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        expect![[r#"
+            client,available,held,total,locked
+            1,1.0000,0.0000,1.0000,false
+            2,0.0000,1.2500,1.2500,false
+        "#]]
+        .assert_eq(&actual);
+    }
+
+    #[test]
+    fn process_csv_reader_wrong_headers() {
+        let mut ledger = Ledger::new();
+        let res = ledger.process_csv_reader(
+            inline_csv!(
+                "type,       tx, client, amount",
+                "deposit,     1,      2,   1.0",
+            )
+            .as_bytes(),
+        );
+        assert!(res.is_err());
+        assert_eq!(ledger, Ledger::new());
+    }
+
+    #[test]
+    fn transaction_history() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   2.0",
+            "deposit,         2,  2,   1.0",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
+            "transfer,        1,  3,   1.0,   2",
+        ))
+        .unwrap();
+        let history: Vec<_> = ledger
+            .transaction_history(ClientId(1))
+            .unwrap()
+            .iter()
+            .map(Transaction::tx_id)
+            .collect();
+        assert_eq!(history, vec![TxId(1), TxId(1), TxId(1), TxId(3)]);
+        let history: Vec<_> = ledger
+            .transaction_history(ClientId(2))
+            .unwrap()
+            .iter()
+            .map(Transaction::tx_id)
+            .collect();
+        assert_eq!(history, vec![TxId(2), TxId(3)]);
+        assert_eq!(ledger.transaction_history(ClientId(3)), None);
+    }
+
+    #[test]
+    fn process_checked() {
+        let mut ledger = Ledger::new();
+        let affected = ledger
+            .process_checked(Transaction::new_deposit(
+                ClientId(1),
+                TxId(1),
+                TxAmount(Dec!(2.0)),
+            ))
+            .unwrap();
+        assert_eq!(affected.iter().collect::<Vec<_>>(), vec![ClientId(1)]);
+        let affected = ledger
+            .process_checked(Transaction::new_transfer(
+                ClientId(1),
+                ClientId(2),
+                TxId(2),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        assert_eq!(
+            affected.iter().collect::<Vec<_>>(),
+            vec![ClientId(1), ClientId(2)]
+        );
+        let error = ledger
+            .process_checked(Transaction::new_dispute(ClientId(1), TxId(3)))
+            .unwrap_err();
+        assert_eq!(error, LedgerError::UnknownTx(ClientId(1), TxId(3)));
+    }
+
+    #[test]
+    fn is_consistent() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   1.0",
+            "withdrawal,      1,  3,   1.5",
+            "dispute,         1,  1",
+            "dispute,         1,  3",
+            "deposit,         2,  4,   1.0",
+            "dispute,         2,  4",
+            "chargeback,      2,  4",
+        ))
+        .unwrap();
+        assert_eq!(ledger.is_consistent(), Ok(()));
+
+        ledger.transaction_state.remove(&(ClientId(1), TxId(2)));
+        ledger.accounts.get_mut(&ClientId(1)).unwrap().held_funds = TxAmount::ZERO;
+        ledger
+            .transaction_state
+            .insert((ClientId(3), TxId(5)), TxState::Processed);
+        assert_eq!(
+            ledger.is_consistent(),
+            Err(vec![
+                InconsistencyError::MissingAmount(ClientId(3), TxId(5)),
+                InconsistencyError::MissingState(ClientId(1), TxId(2)),
+            ])
+        );
+
+        ledger.accounts.get_mut(&ClientId(1)).unwrap().held_funds = TxAmount(Dec!(-1.5));
+        ledger.transaction_state.remove(&(ClientId(3), TxId(5)));
+        ledger
+            .transaction_state
+            .insert((ClientId(1), TxId(2)), TxState::Processed);
+        assert_eq!(
+            ledger.is_consistent(),
+            Err(vec![InconsistencyError::HeldFundsTooLow {
+                client: ClientId(1),
+                held: TxAmount(Dec!(-1.5)),
+                expected: TxAmount(Dec!(-0.5)),
+            },])
+        );
+
+        // Held funds without any dispute must not be negative
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        ledger.accounts.get_mut(&ClientId(1)).unwrap().held_funds = TxAmount(Dec!(-0.5));
+        assert_eq!(
+            ledger.is_consistent(),
+            Err(vec![InconsistencyError::HeldFundsTooLow {
+                client: ClientId(1),
+                held: TxAmount(Dec!(-0.5)),
+                expected: TxAmount::ZERO,
+            }])
+        );
+
+        // Unlocking a charged back account can reveal a legitimately negative total
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "withdrawal,      1,  2,   1.0",
+            "dispute,         1,  1",
+            "chargeback,      1,  1",
+        ))
+        .unwrap();
+        ledger.admin_unlock(ClientId(1), "test").unwrap();
+        assert_eq!(ledger.is_consistent(), Ok(()));
+    }
+
+    #[test]
+    fn evict_finalized_transactions() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         1,  2,   1.0",
+            "deposit,         1,  3,   1.0",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
+            "dispute,         1,  2",
+            "deposit,         2,  4,   1.0",
+            "dispute,         2,  4",
+            "chargeback,      2,  4",
+        ))
+        .unwrap();
+        assert_eq!(ledger.evict_finalized_transactions(), 2);
+        assert_eq!(ledger.transaction_count(), 2);
+        assert_eq!(ledger.get_transaction_state(ClientId(1), TxId(1)), None);
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(1), TxId(2)),
+            Some(TxState::Disputed)
+        );
+        assert_eq!(ledger.is_consistent(), Ok(()));
+        assert_eq!(ledger.evict_finalized_transactions(), 0);
+
+        // Evicted ids cannot be re-used
+        let before = ledger.clone();
+        assert_eq!(
+            ledger.process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(1),
+                TxAmount(Dec!(1.0))
+            )),
+            Err(LedgerError::DuplicateTx(ClientId(1), TxId(1)))
+        );
+        assert_eq!(
+            ledger.process(Transaction::new_transfer(
+                ClientId(1),
+                ClientId(2),
+                TxId(4),
+                TxAmount(Dec!(1.0))
+            )),
+            Err(LedgerError::DuplicateTx(ClientId(2), TxId(4)))
+        );
+        assert_eq!(ledger, before);
+        let json = serde_json::to_string(&ledger).unwrap();
+        assert_eq!(Ledger::load_json(json.as_bytes()).unwrap(), ledger);
+    }
+
+    #[test]
+    fn extend() {
+        let mut ledger = Ledger::new();
+        ledger.extend(vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(2.0))),
+            Transaction::new_dispute(ClientId(1), TxId(3)),
+            Transaction::new_deposit(ClientId(1), TxId(4), TxAmount(Dec!(1.0))),
+        ]);
+        assert_eq!(ledger.last_extend_errors(), 2);
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,2.0,0,2.0,false
+            "#]],
+        );
+        ledger.extend(vec![]);
+        assert_eq!(ledger.last_extend_errors(), 0);
+    }
+
+    #[test]
+    fn from_iterator() {
+        let ledger: Result<Ledger, _> = vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_deposit(ClientId(1), TxId(2), TxAmount(Dec!(1.0))),
+        ]
+        .into_iter()
+        .collect();
+        check_ledger(
+            &ledger.unwrap(),
+            expect![[r#"
+                client,available,held,total,locked
+                1,2.0,0,2.0,false
+            "#]],
+        );
+
+        let error = vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(2.0))),
+            Transaction::new_deposit(ClientId(1), TxId(3), TxAmount(Dec!(1.0))),
+        ]
+        .into_iter()
+        .collect::<Result<Ledger, _>>()
+        .unwrap_err();
+        assert_eq!(error, LedgerError::NotEnoughFunds);
+    }
+
+    #[test]
+    fn with_capacity() {
+        let ledger = Ledger::with_capacity(10, 100);
+        assert!(ledger.accounts.capacity() >= 10);
+        assert!(ledger.transaction_amounts.capacity() >= 100);
+        assert!(ledger.transaction_state.capacity() >= 100);
+        assert_eq!(ledger, Ledger::new());
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut ledger = Ledger::with_capacity(1000, 1000);
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(1),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        let before = ledger.clone();
+        ledger.shrink_to_fit();
+        assert!(ledger.accounts.capacity() < 1000);
+        assert!(ledger.transaction_amounts.capacity() < 1000);
+        assert!(ledger.transaction_state.capacity() < 1000);
+        assert_eq!(ledger, before);
+    }
+
+    #[test]
+    fn diff() {
+        let before = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "deposit,         3,  3,   1.0",
+        ))
+        .unwrap();
+        let mut after = before.clone();
+        let snapshot = before.snapshot();
+        assert!(before.diff(&after).is_empty());
+
+        after
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(4),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        after
+            .process(Transaction::new_deposit(
+                ClientId(4),
+                TxId(5),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        after.accounts.remove(&ClientId(3));
+
+        let account = |funds, creation_order| AccountInfo {
+            available_funds: TxAmount(funds),
+            creation_order,
+            ..Default::default()
+        };
+        assert_eq!(
+            before.diff(&after).changes,
+            vec![
+                AccountChange {
+                    client: ClientId(1),
+                    before: Some(account(Dec!(1.0), 0)),
+                    after: Some(account(Dec!(2.0), 0)),
+                },
+                AccountChange {
+                    client: ClientId(3),
+                    before: Some(account(Dec!(1.0), 2)),
+                    after: None,
+                },
+                AccountChange {
+                    client: ClientId(4),
+                    before: None,
+                    after: Some(account(Dec!(1.0), 3)),
+                },
+            ]
+        );
+        assert_eq!(snapshot.diff(&after), before.diff(&after));
+    }
+
+    #[test]
+    fn known_clients() {
+        let mut ledger = Ledger::new();
+        ledger.extend(vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_deposit(ClientId(3), TxId(2), TxAmount(Dec!(1.0))),
+            // A failed transaction does not create an account
+            Transaction::new_withdrawal(ClientId(2), TxId(3), TxAmount(Dec!(1.0))),
+        ]);
+        let mut clients: Vec<_> = ledger.known_clients().collect();
+        clients.sort();
+        assert_eq!(clients, vec![ClientId(1), ClientId(3)]);
+    }
+
+    #[test]
+    fn has_client_and_transaction() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+        ))
+        .unwrap();
+        assert!(ledger.has_client(ClientId(1)));
+        assert!(ledger.has_client(ClientId(2)));
+        assert!(!ledger.has_client(ClientId(3)));
+        assert!(ledger.has_transaction(ClientId(1), TxId(1)));
+        assert!(ledger.has_transaction(ClientId(2), TxId(2)));
+        assert!(!ledger.has_transaction(ClientId(1), TxId(2)));
+        assert!(!ledger.has_transaction(ClientId(3), TxId(3)));
+    }
+
+    #[test]
+    fn account_ratios() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   3.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+            "deposit,         2,  3,   1.0",
+            "withdrawal,      2,  4,   1.0",
+        ))
+        .unwrap();
+        let account = ledger.get_account(ClientId(1)).unwrap();
+        assert_eq!(account.net_available_ratio(), 0.75);
+        assert_eq!(account.held_ratio(), 0.25);
+        let empty = ledger.get_account(ClientId(2)).unwrap();
+        assert_eq!(empty.net_available_ratio(), 1.0);
+        assert_eq!(empty.held_ratio(), 0.0);
+    }
+
+    #[test]
+    fn account_display() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,  10.0",
+            "deposit,         1,  2,   2.0",
+            "dispute,         1,  2",
+        ))
+        .unwrap();
+        let account = ledger.get_account(ClientId(1)).unwrap();
+        assert_eq!(
+            account.to_string(),
+            "available: 10.0, held: 2.0, total: 12.0, locked: false"
+        );
+        assert_eq!(
+            format!("{account:.2}"),
+            "available: 10.00, held: 2.00, total: 12.00, locked: false"
+        );
+    }
+
+    #[test]
+    fn from_csv_reader() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         2,  1,   2.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+            "deposit,         3,  3,   1.0",
+            "dispute,         3,  3",
+            "chargeback,      3,  3",
+        ))
+        .unwrap();
+        let dump = ledger.dump_csv_to_string().unwrap();
+        let loaded = Ledger::from_csv_reader(dump.as_bytes()).unwrap();
+        assert_eq!(loaded.dump_csv_to_string().unwrap(), dump);
+        assert!(!loaded.has_transaction(ClientId(1), TxId(2)));
+        for (id, info) in ledger.iter_accounts() {
+            assert_eq!(loaded.get_account(id), Some(info));
+        }
+
+        // Negative totals can be legitimate, e.g: after unlocking a charged back account
+        let csv = "client,available,held,total,locked\n1,-1.0,0,-1.0,false\n";
+        assert_eq!(
+            Ledger::from_csv_reader(csv.as_bytes())
+                .unwrap()
+                .is_consistent(),
+            Ok(())
+        );
+        let csv = "client,available,held,total,locked\n1,2.0,-1.0,1.0,false\n";
+        assert!(Ledger::from_csv_reader(csv.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn dump_tsv() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         2,  1,   2.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+        ))
+        .unwrap();
+        let mut tsv = Vec::new();
+        ledger.dump_tsv(&mut tsv).unwrap();
+        expect![[r#"
+            client	available	held	total	locked
+            2	2.0	0	2.0	false
+            1	0.0	1.0	1.0	false
+        "#]]
+        .assert_eq(&String::from_utf8(tsv).unwrap());
+    }
+
+    #[test]
+    fn dump_json_accounts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         2,  1,   2.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+        ))
+        .unwrap();
+        let mut json = Vec::new();
+        ledger.dump_json_accounts(&mut json).unwrap();
+        expect![[r#"[{"client":1,"available":"0.0","held":"1.0","total":"1.0","locked":false},{"client":2,"available":"2.0","held":"0","total":"2.0","locked":false}]"#]]
+            .assert_eq(&String::from_utf8(json).unwrap());
+    }
+
+    #[test]
+    fn duplicate_transaction() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   1.0,",
+            "deposit,         2,  2,   1.0,",
+            "transfer,        1,  3,   0.5,  2",
+        ))
+        .unwrap();
+        let before = ledger.clone();
+        assert_eq!(
+            ledger.process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(1),
+                TxAmount(Dec!(2.0))
+            )),
+            Err(LedgerError::DuplicateTx(ClientId(1), TxId(1)))
+        );
+        assert_eq!(
+            ledger.process(Transaction::new_withdrawal(
+                ClientId(2),
+                TxId(3),
+                TxAmount(Dec!(0.5))
+            )),
+            Err(LedgerError::DuplicateTx(ClientId(2), TxId(3)))
+        );
+        assert_eq!(
+            ledger.process(Transaction::new_transfer(
+                ClientId(2),
+                ClientId(1),
+                TxId(2),
+                TxAmount(Dec!(0.5))
+            )),
+            Err(LedgerError::DuplicateTx(ClientId(2), TxId(2)))
+        );
+        // The same transaction id can be used by different clients
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(2),
+                TxId(1),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        assert_eq!(
+            ledger.get_transaction_amount(ClientId(1), TxId(1)),
+            before.get_transaction_amount(ClientId(1), TxId(1))
+        );
+    }
+
+    #[test]
+    fn process_or_rollback() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+        ))
+        .unwrap();
+        let before = ledger.clone();
+        assert_eq!(
+            ledger.process_or_rollback(Transaction::new_transfer(
+                ClientId(1),
+                ClientId(2),
+                TxId(2),
+                TxAmount(Dec!(2.0))
+            )),
+            Err(LedgerError::NotEnoughFunds)
+        );
+        assert_eq!(ledger, before);
+        ledger
+            .process_or_rollback(Transaction::new_transfer(
+                ClientId(1),
+                ClientId(2),
+                TxId(2),
+                TxAmount(Dec!(0.5)),
+            ))
+            .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.5,0,0.5,false
+                2,0.5,0,0.5,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn process_transfer() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         3,  2,   1.0",
+            "deposit,         3,  3,   1.0",
+            "dispute,         3,  3",
+            "chargeback,      3,  3",
+        ))
+        .unwrap();
+        let before = ledger.clone();
+        assert_eq!(
+            ledger.process_transfer(ClientId(1), ClientId(2), TxId(4), TxAmount(Dec!(2.0))),
+            Err(LedgerError::NotEnoughFunds)
+        );
+        assert_eq!(
+            ledger.process_transfer(ClientId(1), ClientId(3), TxId(4), TxAmount(Dec!(0.5))),
+            Err(LedgerError::FrozenAccount)
+        );
+        assert_eq!(ledger, before);
+        ledger
+            .process_transfer(ClientId(1), ClientId(2), TxId(4), TxAmount(Dec!(0.5)))
+            .unwrap();
+        check_ledger(
+            &ledger,
+            expect![[r#"
+                client,available,held,total,locked
+                1,0.5,0,0.5,false
+                3,1.0,0.0,1.0,true
+                2,0.5,0,0.5,false
+            "#]],
+        );
+    }
+
+    #[test]
+    fn check_withdrawal_allowed() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1.0",
+            "deposit,         2,  2,   1.0",
+            "dispute,         2,  2",
+            "chargeback,      2,  2",
+        ))
+        .unwrap();
+        let account = ledger.get_account(ClientId(1)).unwrap();
+        assert_eq!(
+            account.check_withdrawal_allowed(TxAmount(Dec!(1.0))),
+            Ok(())
+        );
+        assert_eq!(
+            account.check_withdrawal_allowed(TxAmount(Dec!(1.5))),
+            Err(LedgerError::NotEnoughFunds)
+        );
+        assert_eq!(account.available_funds(), TxAmount(Dec!(1.0)));
+        let frozen = ledger.get_account(ClientId(2)).unwrap();
+        assert_eq!(
+            frozen.check_withdrawal_allowed(TxAmount::ZERO),
+            Err(LedgerError::FrozenAccount)
+        );
+    }
+
+    #[test]
+    fn account_statement() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   3.0,",
+            "deposit,         1,  2,   1.0,",
+            "deposit,         1,  3,   1.0,",
+            "deposit,         2,  4,   1.0,",
+            "transfer,        1,  5,   1.0,  2",
+            "dispute,         1,  1,      ,",
+            "resolve,         1,  1,      ,",
+            "dispute,         1,  2,      ,",
+            "dispute,         1,  3,      ,",
+            "chargeback,      1,  3,      ,",
+        ))
+        .unwrap();
+        assert_eq!(
+            ledger.account_statement(ClientId(1)),
+            Some(AccountStatement {
+                client: ClientId(1),
+                available_funds: TxAmount(Dec!(2.0)),
+                held_funds: TxAmount(Dec!(1.0)),
+                total_funds: TxAmount(Dec!(3.0)),
+                locked: true,
+                transaction_count: 4,
+                dispute_count: 3,
+                chargeback_count: 1,
+            })
+        );
+        assert_eq!(
+            ledger
+                .account_statement(ClientId(2))
+                .map(|s| s.transaction_count),
+            Some(2)
+        );
+        assert_eq!(ledger.account_statement(ClientId(3)), None);
+    }
+
+    #[test]
+    fn stats() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   3.0,",
+            "deposit,         1,  2,   1.0,",
+            "deposit,         2,  3,   1.0,",
+            "transfer,        1,  4,   1.0,  3",
+            "dispute,         1,  2,      ,",
+            "dispute,         2,  3,      ,",
+            "chargeback,      2,  3,      ,",
+        ))
+        .unwrap();
+        let stats = ledger.stats();
+        assert_eq!(
+            stats,
+            LedgerStats {
+                num_accounts: 3,
+                num_transactions: 5,
+                num_disputes_pending: 1,
+                num_locked_accounts: 1,
+                total_available: TxAmount(Dec!(3.0)),
+                total_held: TxAmount(Dec!(1.0)),
+            }
+        );
+        assert_eq!(stats.num_accounts, ledger.account_count());
+        assert_eq!(stats.num_transactions, ledger.transaction_count());
+        assert_eq!(
+            stats.num_disputes_pending,
+            ledger.pending_disputes().count()
+        );
+        assert_eq!(stats.num_locked_accounts, ledger.num_locked_accounts());
+        assert_eq!(stats.total_available, ledger.total_available_funds());
+        assert_eq!(stats.total_held, ledger.total_held_funds());
+        assert_eq!(max_balances_ledger().stats().total_available, TxAmount::MAX);
+    }
+
+    #[test]
+    fn observers() {
+        type Events = Vec<(Transaction, LedgerResult<()>)>;
+
+        struct Recorder(std::sync::Arc<std::sync::Mutex<Events>>);
+
+        impl LedgerObserver for Recorder {
+            fn on_transaction(&self, tx: &Transaction, result: &Result<(), LedgerError>) {
+                self.0.lock().unwrap().push((*tx, result.clone()));
+            }
+        }
+
+        let events = std::sync::Arc::default();
+        let mut ledger = Ledger::new();
+        let id = ledger.subscribe(Box::new(Recorder(std::sync::Arc::clone(&events))));
+        let deposit = Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0)));
+        let withdrawal = Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(2.0)));
+        ledger.process(deposit).unwrap();
+        ledger.process_or_rollback(withdrawal).unwrap_err();
+        // Observers are kept when restoring a snapshot
+        ledger.restore(ledger.snapshot());
+        assert!(ledger.unsubscribe(id).is_some());
+        assert!(ledger.unsubscribe(id).is_none());
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(3),
+                TxAmount(Dec!(1.0)),
+            ))
+            .unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (deposit, Ok(())),
+                (withdrawal, Err(LedgerError::NotEnoughFunds))
+            ]
+        );
     }
 
     #[test]
-    fn deposit_single_account() {
-        let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "deposit,         1,  2,   2.0",
-        ))
-        .unwrap();
+    fn ledger_is_unwind_safe() {
+        fn assert_unwind_safe<T: std::panic::UnwindSafe + std::panic::RefUnwindSafe>() {}
+        assert_unwind_safe::<Ledger>();
+    }
+
+    #[test]
+    fn replay_events() {
+        let (ledger, errors) = Ledger::replay_events(vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(2.0))),
+            Transaction::new_deposit(ClientId(2), TxId(3), TxAmount(Dec!(1.0))),
+            Transaction::new_dispute(ClientId(2), TxId(1)),
+        ]);
+        assert_eq!(
+            errors,
+            vec![
+                (2, LedgerError::NotEnoughFunds),
+                (4, LedgerError::UnknownTx(ClientId(2), TxId(1)))
+            ]
+        );
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,3.0,0,3.0,false
+                1,1.0,0,1.0,false
+                2,1.0,0,1.0,false
             "#]],
         );
     }
 
     #[test]
-    fn deposit_multiple_accounts() {
+    fn export_transactions() {
         let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         2,  1,   3.0,",
+            "deposit,         1,  3,   1.0,",
+            "withdrawal,      2,  2,   1.0,",
+            "transfer,        2,  4,   1.0,  1",
+            "dispute,         1,  3,      ,",
+        ))
+        .unwrap();
+        assert_eq!(
+            ledger.export_transactions(),
+            vec![
+                (ClientId(1), TxId(3), TxAmount(Dec!(1.0)), TxState::Disputed),
+                (
+                    ClientId(1),
+                    TxId(4),
+                    TxAmount(Dec!(1.0)),
+                    TxState::Processed
+                ),
+                (
+                    ClientId(2),
+                    TxId(1),
+                    TxAmount(Dec!(3.0)),
+                    TxState::Processed
+                ),
+                (
+                    ClientId(2),
+                    TxId(2),
+                    TxAmount(Dec!(-1.0)),
+                    TxState::Processed
+                ),
+                (
+                    ClientId(2),
+                    TxId(4),
+                    TxAmount(Dec!(-1.0)),
+                    TxState::Processed
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn drain_locked_accounts() {
+        let mut ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
             "deposit,         1,  1,   1.0",
-            "deposit,         2,  2,   1.0",
-            "deposit,         1,  3,   2.0",
+            "deposit,         2,  2,   2.0",
+            "deposit,         2,  3,   1.0",
+            "dispute,         2,  3",
+            "chargeback,      2,  3",
         ))
         .unwrap();
+        let drained = ledger.drain_locked_accounts();
+        assert_eq!(drained.len(), 1);
+        assert!(drained[&ClientId(2)].is_locked());
+        assert_eq!(drained[&ClientId(2)].total_funds(), TxAmount(Dec!(2.0)));
+        assert!(!ledger.has_client(ClientId(2)));
+        assert!(!ledger.has_transaction(ClientId(2), TxId(2)));
+        assert_eq!(ledger.transaction_history(ClientId(2)), None);
+        assert_eq!(ledger.is_consistent(), Ok(()));
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,3.0,0,3.0,false
-                2,1.0,0,1.0,false
+                1,1.0,0,1.0,false
             "#]],
         );
+        assert!(ledger.drain_locked_accounts().is_empty());
     }
 
     #[test]
-    fn deposit_and_withdrawal() {
-        let ledger = process_transactions(inline_csv!(
+    fn resolve_all_pending_disputes() {
+        let mut ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
             "deposit,         1,  1,   1.0",
-            "deposit,         2,  2,   1.0",
-            "deposit,         1,  3,   2.0",
-            "withdrawal,      1,  4,   1.5",
-            "withdrawal,      2,  5,   1.0",
+            "deposit,         1,  2,   2.0",
+            "deposit,         2,  3,   3.0",
+            "dispute,         2,  3",
+            "dispute,         1,  2",
+            "dispute,         1,  1",
+            "resolve,         1,  1",
         ))
         .unwrap();
+        let mut charged_back = ledger.clone();
+        assert_eq!(ledger.resolve_all_pending_disputes(), vec![Ok(()), Ok(())]);
+        assert_eq!(ledger.pending_disputes().count(), 0);
+        assert!(ledger.resolve_all_pending_disputes().is_empty());
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,1.5,0,1.5,false
-                2,0.0,0,0.0,false
+                1,3.0,0.0,3.0,false
+                2,3.0,0.0,3.0,false
             "#]],
         );
-    }
 
-    #[test]
-    fn deposit_and_withdrawal_not_enough_funds() {
-        let error = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         2,  2,   1.0",
-            "withdrawal,      2,  5,   3.0",
-        ))
-        .unwrap_err();
-        assert_eq!(error, LedgerError::NotEnoughFunds);
+        assert_eq!(
+            charged_back.chargeback_all_pending_disputes(),
+            vec![Ok(()), Ok(())]
+        );
+        check_ledger(
+            &charged_back,
+            expect![[r#"
+                client,available,held,total,locked
+                1,1.0,0.0,1.0,true
+                2,0.0,0.0,0.0,true
+            "#]],
+        );
     }
 
     #[test]
-    fn dispute_deposit() {
-        let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
+    fn rename_client() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   2.0,",
+            "deposit,         2,  2,   1.0,",
+            "transfer,        1,  3,   1.0,  2",
+            "dispute,         1,  1,      ,",
         ))
         .unwrap();
+        assert_eq!(
+            ledger.rename_client(ClientId(3), ClientId(4)),
+            Err(LedgerError::UnknownClient(ClientId(3)))
+        );
+        assert_eq!(
+            ledger.rename_client(ClientId(1), ClientId(2)),
+            Err(LedgerError::ClientAlreadyExists(ClientId(2)))
+        );
+        ledger.rename_client(ClientId(1), ClientId(3)).unwrap();
+        assert_eq!(ledger.is_consistent(), Ok(()));
+        assert!(!ledger.has_client(ClientId(1)));
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(3), TxId(1)),
+            Some(TxState::Disputed)
+        );
+        assert_eq!(
+            ledger.transaction_history(ClientId(2)).unwrap()[1],
+            Transaction::new_transfer(ClientId(3), ClientId(2), TxId(3), TxAmount(Dec!(1.0)))
+        );
+        assert_eq!(ledger.transaction_history(ClientId(1)), None);
+        assert_eq!(ledger.transaction_history(ClientId(3)).unwrap().len(), 3);
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,0.0,1.0,1.0,false
+                3,-1.0,2.0,1.0,false
+                2,2.0,0,2.0,false
             "#]],
         );
     }
 
     #[test]
-    fn dispute_withdrawal() {
-        let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "withdrawal,      1,  2,   1.0",
-            "dispute,         1,  2",
+    fn merge_clients() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   2.0,",
+            "deposit,         2,  2,   1.0,",
+            "deposit,         2,  3,   1.0,",
+            "dispute,         2,  3,      ,",
+            "deposit,         3,  4,   1.0,",
+            "transfer,        3,  5,   1.0,  2",
+            "deposit,         4,  1,   1.0,",
+            "deposit,         5,  6,   1.0,",
+            "dispute,         5,  6,      ,",
+            "chargeback,      5,  6,      ,",
         ))
         .unwrap();
+        let before = ledger.clone();
+        assert_eq!(
+            ledger.merge_clients(ClientId(1), ClientId(6)),
+            Err(LedgerError::UnknownClient(ClientId(6)))
+        );
+        assert_eq!(
+            ledger.merge_clients(ClientId(1), ClientId(5)),
+            Err(LedgerError::FrozenAccount)
+        );
+        assert_eq!(
+            ledger.merge_clients(ClientId(1), ClientId(4)),
+            Err(LedgerError::ConflictingTx(ClientId(1), TxId(1)))
+        );
+        assert_eq!(ledger, before);
+
+        ledger.merge_clients(ClientId(1), ClientId(2)).unwrap();
+        assert_eq!(ledger.is_consistent(), Ok(()));
+        assert!(!ledger.has_client(ClientId(2)));
+        assert_eq!(
+            ledger.get_transaction_state(ClientId(1), TxId(3)),
+            Some(TxState::Disputed)
+        );
+        assert_eq!(ledger.transaction_history(ClientId(1)).unwrap().len(), 5);
+        assert_eq!(
+            ledger.transaction_history(ClientId(3)).unwrap()[1],
+            Transaction::new_transfer(ClientId(3), ClientId(1), TxId(5), TxAmount(Dec!(1.0)))
+        );
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,1.0,-1.0,0.0,false
+                1,4.0,1.0,5.0,false
+                3,0.0,0,0.0,false
+                4,1.0,0,1.0,false
+                5,0.0,0.0,0.0,true
             "#]],
         );
     }
 
     #[test]
-    fn resolve_dispute() {
-        let ledger = process_transactions(inline_csv!(
+    fn credit_limit() {
+        let mut ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
             "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "resolve,         1,  1",
         ))
         .unwrap();
+        assert_eq!(
+            ledger.set_credit_limit(ClientId(2), TxAmount(Dec!(1.0))),
+            Err(LedgerError::UnknownClient(ClientId(2)))
+        );
+        assert_eq!(
+            ledger.set_credit_limit(ClientId(1), TxAmount(Dec!(-1.0))),
+            Err(LedgerError::NegativeCreditLimit)
+        );
+        assert_eq!(ledger.available_credit(ClientId(1)), Some(TxAmount::ZERO));
+        ledger
+            .set_credit_limit(ClientId(1), TxAmount(Dec!(2.0)))
+            .unwrap();
+        assert_eq!(ledger.available_credit(ClientId(2)), None);
+        assert_eq!(
+            ledger.available_credit(ClientId(1)),
+            Some(TxAmount(Dec!(2.0)))
+        );
+        ledger
+            .process(Transaction::new_withdrawal(
+                ClientId(1),
+                TxId(2),
+                TxAmount(Dec!(2.5)),
+            ))
+            .unwrap();
+        assert_eq!(
+            ledger.available_credit(ClientId(1)),
+            Some(TxAmount(Dec!(0.5)))
+        );
+        assert_eq!(
+            ledger.process(Transaction::new_withdrawal(
+                ClientId(1),
+                TxId(3),
+                TxAmount(Dec!(0.6))
+            )),
+            Err(LedgerError::NotEnoughFunds)
+        );
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,1.0,0.0,1.0,false
+                1,-1.5,0,-1.5,false
             "#]],
         );
+        // The credit limit is kept when (de)serializing
+        let mut json = Vec::new();
+        ledger.dump_json(&mut json).unwrap();
+        assert_eq!(Ledger::load_json(json.as_slice()).unwrap(), ledger);
     }
 
     #[test]
-    fn chargeback_dispute() {
-        let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "chargeback,      1,  1",
-        ))
-        .unwrap();
+    fn process_transaction_fields() {
+        let mut ledger = Ledger::new();
+        ledger
+            .process_transaction_fields("deposit", 1, 1, Some("2.0"))
+            .unwrap();
+        ledger
+            .process_transaction_fields("Withdrawal", 1, 2, Some("0.5"))
+            .unwrap();
+        ledger
+            .process_transaction_fields("dispute", 1, 1, None)
+            .unwrap();
+        assert_eq!(
+            ledger.process_transaction_fields("refund", 1, 3, Some("1.0")),
+            Err(ProcessError::Parse(ParseError::UnknownTx("refund".into())))
+        );
+        assert_eq!(
+            ledger.process_transaction_fields("deposit", 1, 3, Some("abc")),
+            Err(ProcessError::Amount(crate::TxAmountParseError::Invalid(
+                "abc".into()
+            )))
+        );
+        assert_eq!(
+            ledger.process_transaction_fields("withdrawal", 1, 3, Some("1.0")),
+            Err(ProcessError::Ledger(LedgerError::NotEnoughFunds))
+        );
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,0.0,0.0,0.0,true
+                1,-0.5,2.0,1.5,false
             "#]],
         );
     }
 
     #[test]
-    fn dispute_then_withdrawal() {
-        let error = process_transactions(inline_csv!(
+    fn max_transaction_id() {
+        assert_eq!(Ledger::new().max_transaction_id(), None);
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  3,   1.0",
+            "deposit,         2,  7,   1.0",
+            "deposit,         1,  5,   1.0",
+            "dispute,         1,  5",
+        ))
+        .unwrap();
+        assert_eq!(ledger.max_transaction_id(), Some(TxId(7)));
+
+        // Evicted ids are taken into account, to seed a generator with fresh ids
+        let mut ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
             "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "withdrawal,      1,  2,   1.0",
+            "deposit,         1,  2,   1.0",
+            "dispute,         1,  2",
+            "resolve,         1,  2",
         ))
-        .unwrap_err();
-        assert_eq!(error, LedgerError::NotEnoughFunds);
+        .unwrap();
+        assert_eq!(ledger.evict_finalized_transactions(), 1);
+        assert_eq!(ledger.max_transaction_id(), Some(TxId(2)));
+        let generator = crate::TxIdGenerator::new(ledger.max_transaction_id().unwrap());
+        let tx = generator.next().unwrap();
+        assert_eq!(tx, TxId(3));
+        assert_eq!(
+            ledger.process(Transaction::new_deposit(
+                ClientId(1),
+                tx,
+                TxAmount(Dec!(1.0))
+            )),
+            Ok(())
+        );
     }
 
     #[test]
-    fn dispute_then_resolution() {
-        let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "resolve,         1,  1",
+    fn partition_by_client() {
+        let mut ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         2,  1,   2.0,",
+            "deposit,         1,  2,   1.0,",
+            "transfer,        2,  3,   1.0,  1",
+            "dispute,         1,  2,      ,",
+            "deposit,         3,  4,   1.0,",
+            "dispute,         3,  4,      ,",
+            "chargeback,      3,  4,      ,",
         ))
         .unwrap();
+        ledger.admin_unlock(ClientId(3), "test").unwrap();
+        let partitions = ledger.partition_by_client();
+        assert_eq!(partitions.len(), 3);
         check_ledger(
-            &ledger,
+            &partitions[&ClientId(1)],
             expect![[r#"
                 client,available,held,total,locked
-                1,1.0,0.0,1.0,false
+                1,1.0,1.0,2.0,false
             "#]],
         );
+        assert_eq!(partitions[&ClientId(1)].transaction_count(), 2);
+        assert_eq!(partitions[&ClientId(3)].admin_log().len(), 1);
+        assert!(partitions[&ClientId(2)].admin_log().is_empty());
+
+        let mut merged = Ledger::new();
+        let mut partitions: Vec<_> = partitions.into_values().collect();
+        partitions.sort_by_key(|ledger| ledger.ordered_accounts()[0].1.creation_order());
+        for partition in partitions {
+            assert_eq!(partition.is_consistent(), Ok(()));
+            merged.merge(partition).unwrap();
+        }
+        assert!(ledger.diff(&merged).is_empty());
+        assert_eq!(merged.export_transactions(), ledger.export_transactions());
+        assert_eq!(
+            merged.transaction_history(ClientId(1)),
+            ledger.transaction_history(ClientId(1))
+        );
     }
 
     #[test]
-    fn dispute_then_chargeback() {
+    fn active_accounts() {
         let ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
             "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "chargeback,      1,  1",
+            "deposit,         2,  2,   1.0",
+            "withdrawal,      2,  3,   1.0",
+            "deposit,         3,  4,   1.0",
+            "dispute,         3,  4",
         ))
         .unwrap();
+        let mut active: Vec<_> = ledger.active_accounts().map(|(id, _)| id).collect();
+        active.sort();
+        assert_eq!(active, vec![ClientId(1), ClientId(3)]);
+        let inactive: Vec<_> = ledger.inactive_accounts().map(|(id, _)| id).collect();
+        assert_eq!(inactive, vec![ClientId(2)]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn process_parallel() {
+        let input = inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   1.0,",
+            "deposit,         2,  2,   2.0,",
+            "withdrawal,      1,  3,   2.0,",
+            "dispute,         2,  2,,",
+            "deposit,         3,  4,   1.0,",
+            "transfer,        3,  5,   0.5,  1",
+            "withdrawal,      1,  6,   1.5,",
+            "resolve,         2,  2,,",
+            "withdrawal,      4,  7,   1.0,",
+            "deposit,         4,  8,   1.0,",
+            "dispute,         3,  4,,",
+            "chargeback,      3,  4,,",
+            "deposit,         3,  9,   1.0,",
+        );
+        let txs: Vec<Transaction> = Transaction::configured_csv_reader_builder()
+            .from_reader(input.as_bytes())
+            .into_deserialize()
+            .collect::<csv::Result<_>>()
+            .unwrap();
+
+        let mut expected = Ledger::new();
+        let expected_errors: Vec<_> = txs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &tx)| expected.process(tx).err().map(|err| (index, err)))
+            .collect();
+
+        let mut ledger = Ledger::new();
+        let errors = ledger.process_parallel(txs);
+        assert_eq!(errors, expected_errors);
+        assert_eq!(
+            errors,
+            vec![
+                (2, LedgerError::NotEnoughFunds),
+                (8, LedgerError::NotEnoughFunds),
+                (12, LedgerError::FrozenAccount),
+            ]
+        );
+        assert_eq!(ledger, expected);
+    }
+
+    #[test]
+    fn process_with_timeout() {
+        let txs = [
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.0))),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(2.0))),
+            Transaction::new_deposit(ClientId(2), TxId(3), TxAmount(Dec!(1.0))),
+        ];
+
+        let mut ledger = Ledger::new();
+        assert_eq!(
+            ledger.process_with_timeout(txs, std::time::Duration::ZERO),
+            Err(TimeoutError { processed: 0 })
+        );
+        assert_eq!(ledger, Ledger::new());
+
+        assert_eq!(
+            ledger.process_with_timeout(txs, std::time::Duration::MAX),
+            Ok(3)
+        );
         check_ledger(
             &ledger,
             expect![[r#"
                 client,available,held,total,locked
-                1,0.0,0.0,0.0,true
+                1,1.0,0,1.0,false
+                2,1.0,0,1.0,false
             "#]],
         );
+
+        // The timeout expires while pulling the second transaction, which is not processed
+        let mut ledger = Ledger::new();
+        let timeout = std::time::Duration::from_millis(50);
+        let slow = txs.into_iter().enumerate().map(|(index, tx)| {
+            if index == 1 {
+                std::thread::sleep(timeout);
+            }
+            tx
+        });
+        assert_eq!(
+            ledger.process_with_timeout(slow, timeout),
+            Err(TimeoutError { processed: 1 })
+        );
+        assert!(ledger.has_client(ClientId(1)));
+        assert!(!ledger.has_client(ClientId(2)));
     }
 
     #[test]
-    fn dispute_then_resolution_then_withdrawal() {
+    fn total_deposited_and_withdrawn_for_client() {
         let ledger = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "resolve,         1,  1",
-            "withdrawal,      1,  2,   1.0",
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   3.0,",
+            "deposit,         2,  2,   1.0,",
+            "withdrawal,      1,  3,   0.5,",
+            "transfer,        1,  4,   1.0,  2",
+            "dispute,         1,  1,,",
+            "chargeback,      1,  1,,",
         ))
         .unwrap();
-        check_ledger(
-            &ledger,
-            expect![[r#"
-                client,available,held,total,locked
-                1,0.0,0.0,0.0,false
-            "#]],
+        assert_eq!(
+            ledger.total_deposited_for_client(ClientId(1)),
+            TxAmount(Dec!(3.0))
+        );
+        assert_eq!(
+            ledger.total_withdrawn_for_client(ClientId(1)),
+            TxAmount(Dec!(1.5))
+        );
+        assert_eq!(
+            ledger.total_deposited_for_client(ClientId(2)),
+            TxAmount(Dec!(2.0))
+        );
+        assert_eq!(
+            ledger.total_withdrawn_for_client(ClientId(2)),
+            TxAmount::ZERO
+        );
+        assert_eq!(
+            ledger.total_deposited_for_client(ClientId(3)),
+            TxAmount::ZERO
+        );
+        assert_eq!(
+            ledger.total_withdrawn_for_client(ClientId(3)),
+            TxAmount::ZERO
+        );
+
+        let mut ledger = max_balances_ledger();
+        ledger
+            .process(Transaction::new_withdrawal(
+                ClientId(1),
+                TxId(3),
+                TxAmount::MAX,
+            ))
+            .unwrap();
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(4),
+                TxAmount::MAX,
+            ))
+            .unwrap();
+        assert_eq!(
+            ledger.total_deposited_for_client(ClientId(1)),
+            TxAmount::MAX
         );
     }
 
     #[test]
-    fn dispute_then_chargeback_then_withdrawal() {
-        let error = process_transactions(inline_csv!(
-            "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
-            "chargeback,      1,  1",
-            "withdrawal,      1,  2,   1.0",
+    fn global_totals() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount, to",
+            "deposit,         1,  1,   3.0,",
+            "deposit,         2,  2,   1.0,",
+            "withdrawal,      1,  3,   0.5,",
+            "transfer,        1,  4,   1.0,  2",
+            "dispute,         1,  1,,",
+            "chargeback,      1,  1,,",
+            "withdrawal,      2,  5,   0.25,",
+            "dispute,         2,  5,,",
+            "chargeback,      2,  5,,",
+            "deposit,         3,  6,   2.0,",
+            "dispute,         3,  6,,",
         ))
-        .unwrap_err();
-        assert_eq!(error, LedgerError::FrozenAccount);
+        .unwrap();
+        let deposited = ledger.total_deposited();
+        let withdrawn = ledger.total_withdrawn();
+        let chargedback = ledger.total_chargedback();
+        assert_eq!(deposited, TxAmount(Dec!(7.0)));
+        assert_eq!(withdrawn, TxAmount(Dec!(1.75)));
+        assert_eq!(chargedback, TxAmount(Dec!(2.75)));
+        assert_eq!(
+            deposited - withdrawn - chargedback,
+            ledger.total_available_funds() + ledger.total_held_funds()
+        );
+
+        let mut ledger = max_balances_ledger();
+        assert_eq!(ledger.total_deposited(), TxAmount::MAX);
+        for id in 1..=2 {
+            ledger
+                .process(Transaction::new_withdrawal(
+                    ClientId(id),
+                    TxId(10 + u32::from(id)),
+                    TxAmount::MAX,
+                ))
+                .unwrap();
+            ledger
+                .process(Transaction::new_dispute(ClientId(id), TxId(id.into())))
+                .unwrap();
+            ledger
+                .process(Transaction::new_chargeback(ClientId(id), TxId(id.into())))
+                .unwrap();
+        }
+        assert_eq!(ledger.total_withdrawn(), TxAmount::MAX);
+        assert_eq!(ledger.total_chargedback(), TxAmount::MAX);
     }
 
     #[test]
-    fn dispute_then_deposit_then_dispute_then_resolve_then_chargeback() {
+    fn filter_accounts() {
         let ledger = process_transactions(inline_csv!(
             "type,       client, tx, amount",
-            "deposit,         1,  1,   1.0",
-            "dispute,         1,  1",
+            "deposit,         3,  1,   3.0",
             "deposit,         1,  2,   1.0",
-            "dispute,         1,  2",
-            "resolve,         1,  2",
-            "chargeback,      1,  1",
+            "deposit,         2,  3,   2.0",
+            "dispute,         2,  3",
+        ))
+        .unwrap();
+        let ids = |accounts: Vec<(ClientId, &AccountInfo)>| -> Vec<_> {
+            accounts.into_iter().map(|(id, _)| id).collect()
+        };
+        assert_eq!(
+            ids(ledger.filter_accounts(|_, info| info.total_funds() > TxAmount(Dec!(1.0)))),
+            vec![ClientId(3), ClientId(2)]
+        );
+        assert_eq!(
+            ids(ledger.filter_accounts(|_, info| info.held_funds().is_positive())),
+            vec![ClientId(2)]
+        );
+        assert_eq!(
+            ids(ledger.filter_accounts(|id, _| id > ClientId(1))),
+            vec![ClientId(3), ClientId(2)]
+        );
+        assert!(ledger
+            .filter_accounts(|_, info| info.is_locked())
+            .is_empty());
+    }
+
+    #[test]
+    fn overflow_large_amounts() {
+        let ledger = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   1701411834604692317316873037158841.05727",
         ))
         .unwrap();
         check_ledger(
             &ledger,
             expect![[r#"
-            client,available,held,total,locked
-            1,1.0,0.0,1.0,true
-        "#]],
+                client,available,held,total,locked
+                1,1701411834604692317316873037158841.0573,0,1701411834604692317316873037158841.0573,false
+            "#]],
+        );
+
+        let error = process_transactions(inline_csv!(
+            "type,       client, tx, amount",
+            "deposit,         1,  1,   170141183460469231731687303715884105727",
+        ))
+        .unwrap_err();
+        assert_eq!(error, LedgerError::Overflow);
+    }
+
+    #[test]
+    fn overflow_held_funds() {
+        let max = TxAmount::MAX;
+        let mut ledger = Ledger::new();
+        for tx in [
+            Transaction::new_deposit(ClientId(1), TxId(1), max),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), max),
+            Transaction::new_deposit(ClientId(1), TxId(3), max),
+            Transaction::new_dispute(ClientId(1), TxId(1)),
+        ] {
+            ledger.process(tx).unwrap();
+        }
+        let before = ledger.clone();
+        // Held funds would overflow
+        assert_eq!(
+            ledger.process(Transaction::new_dispute(ClientId(1), TxId(3))),
+            Err(LedgerError::Overflow)
+        );
+        // Total funds would overflow, even though available funds would not
+        assert_eq!(
+            ledger.process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(4),
+                TxAmount(Dec!(1))
+            )),
+            Err(LedgerError::Overflow)
+        );
+        assert_eq!(ledger, before);
+        assert_eq!(ledger.get_account(ClientId(1)).unwrap().total_funds(), max);
+    }
+
+    #[test]
+    fn overflow_merge() {
+        let mut ledger = Ledger::new();
+        ledger
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(1),
+                TxAmount::MAX,
+            ))
+            .unwrap();
+        let mut other = Ledger::new();
+        other
+            .process(Transaction::new_deposit(
+                ClientId(1),
+                TxId(2),
+                TxAmount::MAX,
+            ))
+            .unwrap();
+        let before = ledger.clone();
+        assert_eq!(ledger.merge(other.clone()), Err(LedgerError::Overflow));
+        assert_eq!(ledger, before);
+        other
+            .process(Transaction::new_deposit(
+                ClientId(2),
+                TxId(3),
+                TxAmount::MAX,
+            ))
+            .unwrap();
+        assert_eq!(
+            other.merge_clients(ClientId(1), ClientId(2)),
+            Err(LedgerError::Overflow)
         );
     }
 
+    #[test]
+    fn overflow_load() {
+        let json =
+            r#"{"available":"17014118346046923173168730371588410.5727","held":"1","locked":false}"#;
+        assert!(serde_json::from_str::<AccountInfo>(json).is_err());
+        let csv = "client,available,held,total,locked\n\
+                   1,17014118346046923173168730371588410.5727,1,0,false\n";
+        assert!(Ledger::from_csv_reader(csv.as_bytes()).is_err());
+    }
+
     #[test]
     fn unknown_client_and_transaction() {
         let error = process_transactions(inline_csv!(
@@ -511,7 +4767,7 @@ mod test {
             "dispute,         2,  2",
         ))
         .unwrap_err();
-        assert_eq!(error, LedgerError::UnknownTx(ClientId(2), TxId(2)));
+        assert_eq!(error, LedgerError::UnknownClient(ClientId(2)));
     }
 
     #[test]
@@ -522,7 +4778,7 @@ mod test {
             "dispute,         2,  1",
         ))
         .unwrap_err();
-        assert_eq!(error, LedgerError::UnknownTx(ClientId(2), TxId(1)));
+        assert_eq!(error, LedgerError::UnknownClient(ClientId(2)));
     }
 
     #[test]
@@ -596,3 +4852,103 @@ mod test {
         assert_eq!(error, LedgerError::NotDisputed);
     }
 }
+
+#[cfg(test)]
+mod proptest_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Use few clients and transactions, so that disputes are likely to reference existing ones
+    fn client() -> impl Strategy<Value = ClientId> {
+        (1..4_u16).prop_map(ClientId)
+    }
+
+    fn tx_id() -> impl Strategy<Value = TxId> {
+        (1..20_u32).prop_map(TxId)
+    }
+
+    fn amount() -> impl Strategy<Value = TxAmount> {
+        (1..10_000_000_i64).prop_map(|coeff| TxAmount(fpdec::Decimal::new_raw(coeff.into(), 4)))
+    }
+
+    fn value_transaction() -> impl Strategy<Value = Transaction> {
+        prop_oneof![
+            (client(), tx_id(), amount())
+                .prop_map(|(client, tx, amount)| Transaction::new_deposit(client, tx, amount)),
+            (client(), tx_id(), amount())
+                .prop_map(|(client, tx, amount)| Transaction::new_withdrawal(client, tx, amount)),
+            (client(), client(), tx_id(), amount()).prop_map(|(from, to, tx, amount)| {
+                Transaction::new_transfer(from, to, tx, amount)
+            }),
+        ]
+    }
+
+    fn transaction() -> impl Strategy<Value = Transaction> {
+        prop_oneof![
+            3 => value_transaction(),
+            1 => (client(), tx_id()).prop_map(|(client, tx)| Transaction::new_dispute(client, tx)),
+            1 => (client(), tx_id()).prop_map(|(client, tx)| Transaction::new_resolve(client, tx)),
+            1 => (client(), tx_id())
+                .prop_map(|(client, tx)| Transaction::new_chargeback(client, tx)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn balances_are_consistent(transactions in prop::collection::vec(transaction(), 0..100)) {
+            let mut ledger = Ledger::new();
+            for tx in transactions {
+                let before = ledger.clone();
+                let _ = ledger.process(tx);
+                for (client, info) in ledger.iter_accounts() {
+                    prop_assert_eq!(info.available_funds() + info.held_funds(), info.total_funds());
+                    // A locked account's balance never changes
+                    if let Some(previous) = before.get_account(client).filter(|info| info.is_locked()) {
+                        prop_assert_eq!(previous, info);
+                    }
+                }
+                prop_assert_eq!(ledger.is_consistent(), Ok(()));
+            }
+        }
+
+        #[cfg(feature = "rayon")]
+        #[test]
+        fn process_parallel_matches_process(
+            previous in prop::collection::vec(transaction(), 0..50),
+            evict in any::<bool>(),
+            transactions in prop::collection::vec(transaction(), 0..100),
+        ) {
+            let mut expected = Ledger::new();
+            for tx in previous {
+                let _ = expected.process(tx);
+            }
+            if evict {
+                expected.evict_finalized_transactions();
+            }
+            let mut ledger = expected.clone();
+            let expected_errors: Vec<_> = transactions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, &tx)| expected.process(tx).err().map(|err| (index, err)))
+                .collect();
+            prop_assert_eq!(ledger.process_parallel(transactions), expected_errors);
+            prop_assert_eq!(ledger, expected);
+        }
+
+        // Disputing a withdrawal, or a deposit whose funds were since withdrawn, can bring the
+        // balances below zero, only check for value transactions.
+        #[test]
+        fn balances_are_non_negative(
+            transactions in prop::collection::vec(value_transaction(), 0..100)
+        ) {
+            let mut ledger = Ledger::new();
+            for tx in transactions {
+                let _ = ledger.process(tx);
+                for (_, info) in ledger.iter_accounts() {
+                    prop_assert!(!info.total_funds().is_negative());
+                    prop_assert!(!info.held_funds().is_negative());
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,8 @@
+#[cfg(feature = "async")]
+pub mod async_ledger;
+#[cfg(feature = "async")]
+pub use crate::async_ledger::*;
+
 pub mod core;
 pub use crate::core::*;
 
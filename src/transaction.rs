@@ -4,17 +4,19 @@ use crate::{
     ParseError,
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// A generic [Transaction].
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
-#[serde(try_from = "TransactionRecord")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(try_from = "TransactionRecord", into = "TransactionRecord")]
 pub enum Transaction {
     Deposit(Deposit),
     Withdrawal(Withdrawal),
     Dispute(Dispute),
     Resolve(Resolve),
     Chargeback(Chargeback),
+    Transfer(Transfer),
 }
 
 impl Transaction {
@@ -30,16 +32,225 @@ impl Transaction {
             .flexible(true);
         builder
     }
+
+    /// Build a [csv::WriterBuilder] configured to write a CSV formatted [Transaction] stream which
+    /// can be read back using [Transaction::configured_csv_reader_builder].
+    pub fn configured_csv_writer_builder() -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            // Write header output
+            .has_headers(true)
+            // Use the default delimiter and quoting rules expected by the reader
+            .delimiter(b',')
+            .quote_style(csv::QuoteStyle::Necessary)
+            // Allow fields which are not relevant to a transaction to be omitted
+            .flexible(true);
+        builder
+    }
+
+    /// Create a new [Deposit] transaction.
+    pub fn new_deposit(client: ClientId, tx: TxId, amount: TxAmount) -> Self {
+        Transaction::Deposit(Deposit { client, tx, amount })
+    }
+
+    /// Create a new [Withdrawal] transaction.
+    pub fn new_withdrawal(client: ClientId, tx: TxId, amount: TxAmount) -> Self {
+        Transaction::Withdrawal(Withdrawal { client, tx, amount })
+    }
+
+    /// Create a new [Dispute] transaction.
+    pub fn new_dispute(client: ClientId, tx: TxId) -> Self {
+        Transaction::Dispute(Dispute { client, tx })
+    }
+
+    /// Create a new [Resolve] transaction.
+    pub fn new_resolve(client: ClientId, tx: TxId) -> Self {
+        Transaction::Resolve(Resolve { client, tx })
+    }
+
+    /// Create a new [Chargeback] transaction.
+    pub fn new_chargeback(client: ClientId, tx: TxId) -> Self {
+        Transaction::Chargeback(Chargeback { client, tx })
+    }
+
+    /// Create a new [Transfer] transaction.
+    pub fn new_transfer(from: ClientId, to: ClientId, tx: TxId, amount: TxAmount) -> Self {
+        Transaction::Transfer(Transfer {
+            from,
+            to,
+            tx,
+            amount,
+        })
+    }
+
+    // Build a [Transaction] from its raw fields, as if it was read from CSV
+    pub(crate) fn from_fields(
+        type_: &str,
+        client: ClientId,
+        tx: TxId,
+        amount: Option<TxAmount>,
+    ) -> Result<Self, ParseError> {
+        Transaction::try_from(TransactionRecord {
+            type_: type_.into(),
+            client,
+            tx,
+            amount,
+            to: None,
+            row: 0,
+        })
+    }
+
+    /// Read all transactions from a CSV stream, as configured by
+    /// [Transaction::configured_csv_reader_builder]. Unlike deserializing the records directly,
+    /// the resulting errors report the 1-based row of the offending record.
+    pub fn read_csv<R: std::io::Read>(
+        reader: &mut csv::Reader<R>,
+    ) -> csv::Result<impl Iterator<Item = csv::Result<Transaction>> + '_> {
+        let headers = reader.headers()?.clone();
+        Ok(reader.records().zip(1..).map(move |(record, row)| {
+            let record = record?;
+            let mut parsed: TransactionRecord = record.deserialize(Some(&headers))?;
+            parsed.row = row;
+            Transaction::try_from(parsed)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err).into())
+        }))
+    }
+
+    /// The header of the CSV representation of a [Transaction], see [Transaction::to_csv_record].
+    pub const CSV_HEADER: [&'static str; 5] = ["type", "client", "tx", "amount", "to"];
+
+    /// Check that the headers of a CSV stream match [Transaction::CSV_HEADER], in order. The
+    /// trailing `to` column may be omitted. Headers which cannot be read are reported with their
+    /// underlying error, wrong headers as an [std::io::ErrorKind::InvalidData] error wrapping
+    /// [ParseError::WrongHeaders].
+    pub fn validate_csv_headers<R: std::io::Read>(reader: &mut csv::Reader<R>) -> csv::Result<()> {
+        let got: Vec<String> = reader.headers()?.iter().map(Into::into).collect();
+        let expected = &Self::CSV_HEADER;
+        // The `to` column is only used by transfers, it may be omitted
+        if got != expected[..] && got != expected[..expected.len() - 1] {
+            let err = ParseError::WrongHeaders {
+                expected: expected.iter().map(|&s| s.into()).collect(),
+                got,
+            };
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err).into());
+        }
+        Ok(())
+    }
+
+    /// Serialize a [Transaction] to a CSV record matching [Transaction::CSV_HEADER], which can be
+    /// read back using [Transaction::configured_csv_reader_builder]. Fields which are not
+    /// relevant to the transaction are left empty.
+    pub fn to_csv_record(&self) -> [String; 5] {
+        let to = match self {
+            Transaction::Transfer(Transfer { to, .. }) => to.to_string(),
+            _ => String::new(),
+        };
+        [
+            self.type_name().into(),
+            self.client_id().to_string(),
+            self.tx_id().to_string(),
+            self.amount()
+                .map(|amount| amount.to_string())
+                .unwrap_or_default(),
+            to,
+        ]
+    }
+
+    /// Whether the transaction carries an amount, i.e: [Deposit], [Withdrawal], and [Transfer].
+    pub fn is_value_transaction(&self) -> bool {
+        match self {
+            Transaction::Deposit(_) | Transaction::Withdrawal(_) | Transaction::Transfer(_) => true,
+            Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_) => false,
+        }
+    }
+
+    /// Whether the transaction references a past transaction, i.e: [Dispute], [Resolve], and
+    /// [Chargeback].
+    pub fn is_reference_transaction(&self) -> bool {
+        !self.is_value_transaction()
+    }
+
+    // The name of the transaction type, as used in its serialized form
+    fn type_name(&self) -> &'static str {
+        match self {
+            Transaction::Deposit(_) => "deposit",
+            Transaction::Withdrawal(_) => "withdrawal",
+            Transaction::Dispute(_) => "dispute",
+            Transaction::Resolve(_) => "resolve",
+            Transaction::Chargeback(_) => "chargeback",
+            Transaction::Transfer(_) => "transfer",
+        }
+    }
+
+    /// The client whose account is affected by the transaction, the source account for
+    /// a [Transfer].
+    pub fn client_id(&self) -> ClientId {
+        match self {
+            Transaction::Deposit(Deposit { client, .. })
+            | Transaction::Withdrawal(Withdrawal { client, .. })
+            | Transaction::Dispute(Dispute { client, .. })
+            | Transaction::Resolve(Resolve { client, .. })
+            | Transaction::Chargeback(Chargeback { client, .. })
+            | Transaction::Transfer(Transfer { from: client, .. }) => *client,
+        }
+    }
+
+    /// The id of the transaction, or of the transaction it references.
+    pub fn tx_id(&self) -> TxId {
+        match self {
+            Transaction::Deposit(Deposit { tx, .. })
+            | Transaction::Withdrawal(Withdrawal { tx, .. })
+            | Transaction::Dispute(Dispute { tx, .. })
+            | Transaction::Resolve(Resolve { tx, .. })
+            | Transaction::Chargeback(Chargeback { tx, .. })
+            | Transaction::Transfer(Transfer { tx, .. }) => *tx,
+        }
+    }
+
+    /// The amount of the transaction, if it has one.
+    pub fn amount(&self) -> Option<TxAmount> {
+        match self {
+            Transaction::Deposit(Deposit { amount, .. })
+            | Transaction::Withdrawal(Withdrawal { amount, .. })
+            | Transaction::Transfer(Transfer { amount, .. }) => Some(*amount),
+            Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_) => None,
+        }
+    }
 }
 
-// A type used to deserialize [Transaction] from an input CSV stream.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+// A type used to (de)serialize [Transaction] from/to an input CSV stream.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 struct TransactionRecord<'a> {
-    #[serde(rename = "type")]
-    type_: &'a str,
+    // Borrow from the input when possible, but allow owned values for non-borrowing deserializers
+    #[serde(rename = "type", borrow)]
+    type_: std::borrow::Cow<'a, str>,
     client: ClientId,
     tx: TxId,
+    // Fields are never skipped when serializing, as it is not supported by non self-describing
+    // formats
     amount: Option<TxAmount>,
+    // Only used by transfers, can be omitted from the input
+    to: Option<ClientId>,
+    // The 1-based row of the record in its input, or 0 if unknown, used for error reporting
+    #[serde(skip)]
+    row: usize,
+}
+
+impl From<Transaction> for TransactionRecord<'static> {
+    fn from(value: Transaction) -> Self {
+        let to = match value {
+            Transaction::Transfer(Transfer { to, .. }) => Some(to),
+            _ => None,
+        };
+        Self {
+            type_: value.type_name().into(),
+            client: value.client_id(),
+            tx: value.tx_id(),
+            amount: value.amount(),
+            to,
+            row: 0,
+        }
+    }
 }
 
 impl TryFrom<TransactionRecord<'_>> for Transaction {
@@ -51,21 +262,48 @@ impl TryFrom<TransactionRecord<'_>> for Transaction {
             client,
             tx,
             amount,
+            to,
+            row,
         } = value;
+        let missing_amount = || ParseError::MissingAmount {
+            type_: type_.to_string(),
+            row,
+        };
+        let positive_amount = |amount: Option<TxAmount>| {
+            let amount = amount.ok_or_else(missing_amount)?;
+            if !amount.is_positive() {
+                return Err(ParseError::NonPositiveAmount {
+                    type_: type_.to_string(),
+                    row,
+                });
+            }
+            Ok(amount)
+        };
 
-        let transaction = match type_ {
+        // Third-party exports do not always agree on casing
+        let transaction = match type_.to_ascii_lowercase().as_str() {
             "deposit" => {
-                let amount = amount.ok_or(ParseError::MissingAmount)?;
+                let amount = positive_amount(amount)?;
                 Transaction::Deposit(Deposit { client, tx, amount })
             }
             "withdrawal" => {
-                let amount = amount.ok_or(ParseError::MissingAmount)?;
+                let amount = positive_amount(amount)?;
                 Transaction::Withdrawal(Withdrawal { client, tx, amount })
             }
             "dispute" => Transaction::Dispute(Dispute { client, tx }),
             "resolve" => Transaction::Resolve(Resolve { client, tx }),
             "chargeback" => Transaction::Chargeback(Chargeback { client, tx }),
-            _ => return Err(ParseError::UnknownTx(type_.into())),
+            "transfer" => {
+                let amount = positive_amount(amount)?;
+                let to = to.ok_or(ParseError::MissingDestination { row })?;
+                Transaction::Transfer(Transfer {
+                    from: client,
+                    to,
+                    tx,
+                    amount,
+                })
+            }
+            _ => return Err(ParseError::UnknownTx(type_.into_owned())),
         };
         Ok(transaction)
     }
@@ -73,6 +311,7 @@ impl TryFrom<TransactionRecord<'_>> for Transaction {
 
 /// Deposit funds into an account, i.e: increase its balance by the amount given.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Deposit {
     pub client: ClientId,
     pub tx: TxId,
@@ -82,6 +321,7 @@ pub struct Deposit {
 /// Withdraw funds from an account, i.e: the opposite of a [Deposit]. It is not allowed to withdraw
 /// more than is available on the given account, and should result in a no-op.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Withdrawal {
     pub client: ClientId,
     pub tx: TxId,
@@ -92,6 +332,7 @@ pub struct Withdrawal {
 /// corresponding to the given transaction into a held funds envelop by transfering it from their
 /// available funds. If the given transaction does not exist, this results in a no-op.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Dispute {
     pub client: ClientId,
     pub tx: TxId,
@@ -101,6 +342,7 @@ pub struct Dispute {
 /// back to the available funds. If either the given transaction does not exist, or is not
 /// disputed, this results in a no-op.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Resolve {
     pub client: ClientId,
     pub tx: TxId,
@@ -111,14 +353,28 @@ pub struct Resolve {
 /// either the transaction does not exist, or is not disputed, this results in a no-op and the
 /// account is *not* frozen.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Chargeback {
     pub client: ClientId,
     pub tx: TxId,
 }
 
+/// Move funds from one account to another, i.e: a [Withdrawal] from the `from` account and
+/// a [Deposit] into the `to` account. Both sides of the transfer are applied atomically: if either
+/// of them fails, this results in a no-op.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Transfer {
+    pub from: ClientId,
+    pub to: ClientId,
+    pub tx: TxId,
+    pub amount: TxAmount,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use expect_test::expect;
     use fpdec::{Dec, Decimal};
 
     fn parse_transaction(input: &str) -> Transaction {
@@ -188,6 +444,326 @@ mod test {
         );
     }
 
+    #[test]
+    fn deserialize_transfer() {
+        let data = "type,client,tx,amount,to\ntransfer,1,2,3.0,4";
+        assert_eq!(
+            parse_transaction(data),
+            Transaction::Transfer(Transfer {
+                from: ClientId(1),
+                to: ClientId(4),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(3.0))
+            }),
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        let deposit = parse_transaction("type,client,tx,amount\ndeposit,1,2,3.0");
+        assert_eq!(deposit.client_id(), ClientId(1));
+        assert_eq!(deposit.tx_id(), TxId(2));
+        assert_eq!(deposit.amount(), Some(TxAmount(Dec!(3.0))));
+        let dispute = parse_transaction("type,client,tx,amount\ndispute,1,2");
+        assert_eq!(dispute.client_id(), ClientId(1));
+        assert_eq!(dispute.tx_id(), TxId(2));
+        assert_eq!(dispute.amount(), None);
+        assert!(deposit.is_value_transaction() && !deposit.is_reference_transaction());
+        assert!(!dispute.is_value_transaction() && dispute.is_reference_transaction());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let transactions = vec![
+            Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: TxAmount(Dec!(12.0000)),
+            }),
+            Transaction::Withdrawal(Withdrawal {
+                client: ClientId(3),
+                tx: TxId(4),
+                amount: TxAmount(Dec!(42.27)),
+            }),
+            Transaction::Dispute(Dispute {
+                client: ClientId(5),
+                tx: TxId(6),
+            }),
+            Transaction::Resolve(Resolve {
+                client: ClientId(7),
+                tx: TxId(8),
+            }),
+            Transaction::Chargeback(Chargeback {
+                client: ClientId(9),
+                tx: TxId(10),
+            }),
+            Transaction::Transfer(Transfer {
+                from: ClientId(11),
+                to: ClientId(12),
+                tx: TxId(13),
+                amount: TxAmount(Dec!(1.5)),
+            }),
+        ];
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(Transaction::CSV_HEADER).unwrap();
+        for tx in transactions.iter() {
+            writer.write_record(tx.to_csv_record()).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+        let rdr = Transaction::configured_csv_reader_builder().from_reader(data.as_slice());
+        let parsed: Result<Vec<Transaction>, _> = rdr.into_deserialize().collect();
+        assert_eq!(parsed.unwrap(), transactions);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let deposit = Transaction::Deposit(Deposit {
+            client: ClientId(1),
+            tx: TxId(2),
+            amount: TxAmount(Dec!(3.0)),
+        });
+        let dispute = Transaction::Dispute(Dispute {
+            client: ClientId(1),
+            tx: TxId(2),
+        });
+        let json = serde_json::to_string(&[deposit, dispute]).unwrap();
+        expect![[r#"[{"type":"deposit","client":1,"tx":2,"amount":"3.0","to":null},{"type":"dispute","client":1,"tx":2,"amount":null,"to":null}]"#]]
+            .assert_eq(&json);
+        let parsed: Vec<Transaction> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, vec![deposit, dispute]);
+    }
+
+    #[test]
+    fn validate_headers() {
+        let validate = |input: &[u8]| {
+            let mut rdr = Transaction::configured_csv_reader_builder().from_reader(input);
+            Transaction::validate_csv_headers(&mut rdr)
+        };
+        let wrong_headers = |input: &[u8]| match validate(input).unwrap_err().into_kind() {
+            csv::ErrorKind::Io(err) => *err.into_inner().unwrap().downcast::<ParseError>().unwrap(),
+            kind => panic!("unexpected error: {:?}", kind),
+        };
+        assert!(validate(b"type, client, tx, amount\n").is_ok());
+        assert!(validate(b"type,client,tx,amount,to\n").is_ok());
+        assert_eq!(
+            wrong_headers(b"type,tx,client,amount\n"),
+            ParseError::WrongHeaders {
+                expected: vec![
+                    "type".into(),
+                    "client".into(),
+                    "tx".into(),
+                    "amount".into(),
+                    "to".into()
+                ],
+                got: vec!["type".into(), "tx".into(), "client".into(), "amount".into()],
+            }
+        );
+        // Headers which cannot be read are not reported as wrong headers
+        assert!(matches!(
+            validate(b"type,client,tx,\xffamount\n").unwrap_err().kind(),
+            csv::ErrorKind::Utf8 { .. }
+        ));
+    }
+
+    #[test]
+    fn deserialize_case_insensitive() {
+        let expected = Transaction::Deposit(Deposit {
+            client: ClientId(1),
+            tx: TxId(2),
+            amount: TxAmount(Dec!(3.0)),
+        });
+        assert_eq!(
+            parse_transaction("type,client,tx,amount\nDEPOSIT,1,2,3.0"),
+            expected
+        );
+        assert_eq!(
+            parse_transaction("type,client,tx,amount\nDePoSiT,1,2,3.0"),
+            expected
+        );
+    }
+
+    #[test]
+    fn deserialize_unknown_type() {
+        let data = "type,client,tx,amount\nRefund,1,2,3.0";
+        let rdr = Transaction::configured_csv_reader_builder().from_reader(data.as_bytes());
+        let err = rdr
+            .into_deserialize::<Transaction>()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unknown transaction type 'Refund'"));
+    }
+
+    #[test]
+    fn read_csv_missing_amount() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2";
+        let mut rdr = Transaction::configured_csv_reader_builder().from_reader(data.as_bytes());
+        let results: Vec<_> = Transaction::read_csv(&mut rdr).unwrap().collect();
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ParseError::MissingAmount {
+                type_: "withdrawal".into(),
+                row: 2
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn read_csv_missing_destination() {
+        let data = "type,client,tx,amount,to\ndeposit,1,1,1.0,\ntransfer,1,2,1.0,";
+        let mut rdr = Transaction::configured_csv_reader_builder().from_reader(data.as_bytes());
+        let results: Vec<_> = Transaction::read_csv(&mut rdr).unwrap().collect();
+        assert!(results[0].is_ok());
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            ParseError::MissingDestination { row: 2 }.to_string()
+        );
+    }
+
+    #[test]
+    fn read_csv_non_positive_amount() {
+        let data = "type,client,tx,amount,to\n\
+                    deposit,1,1,0.0,\n\
+                    withdrawal,1,2,0,\n\
+                    deposit,1,3,0.0001,\n\
+                    transfer,1,4,-5.0,2\n\
+                    transfer,1,5,0,2";
+        let mut rdr = Transaction::configured_csv_reader_builder().from_reader(data.as_bytes());
+        let results: Vec<_> = Transaction::read_csv(&mut rdr).unwrap().collect();
+        assert_eq!(
+            results[0].as_ref().unwrap_err().to_string(),
+            ParseError::NonPositiveAmount {
+                type_: "deposit".into(),
+                row: 1
+            }
+            .to_string()
+        );
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            ParseError::NonPositiveAmount {
+                type_: "withdrawal".into(),
+                row: 2
+            }
+            .to_string()
+        );
+        assert!(results[2].is_ok());
+        for (result, row) in results[3..].iter().zip(4..) {
+            assert_eq!(
+                result.as_ref().unwrap_err().to_string(),
+                ParseError::NonPositiveAmount {
+                    type_: "transfer".into(),
+                    row
+                }
+                .to_string()
+            );
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_transactions() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let mut u = Unstructured::new(&bytes);
+        for _ in 0..100 {
+            let tx = Transaction::arbitrary(&mut u).unwrap();
+            if let Some(amount) = tx.amount() {
+                assert!(amount.is_positive());
+                assert!(amount.is_within_precision());
+            }
+        }
+        // Exhausted input would otherwise default to a zero amount
+        let mut u = Unstructured::new(&[]);
+        assert!(TxAmount::arbitrary(&mut u).unwrap().is_positive());
+    }
+
+    #[test]
+    fn try_from_record_errors() {
+        let record = |type_: &'static str, amount, to| TransactionRecord {
+            type_: type_.into(),
+            client: ClientId(1),
+            tx: TxId(2),
+            amount,
+            to,
+            row: 3,
+        };
+        assert_eq!(
+            Transaction::try_from(record("deposit", None, None)),
+            Err(ParseError::MissingAmount {
+                type_: "deposit".into(),
+                row: 3
+            })
+        );
+        assert_eq!(
+            Transaction::try_from(record("transfer", Some(TxAmount(Dec!(1.0))), None)),
+            Err(ParseError::MissingDestination { row: 3 })
+        );
+        assert_eq!(
+            Transaction::try_from(record("refund", None, None)),
+            Err(ParseError::UnknownTx("refund".into()))
+        );
+    }
+
+    #[test]
+    fn csv_writer_round_trip() {
+        let transactions = vec![
+            Transaction::new_deposit(ClientId(1), TxId(1), TxAmount(Dec!(1.5))),
+            Transaction::new_withdrawal(ClientId(1), TxId(2), TxAmount(Dec!(0.5))),
+            Transaction::new_dispute(ClientId(1), TxId(1)),
+            Transaction::new_transfer(ClientId(1), ClientId(2), TxId(3), TxAmount(Dec!(1))),
+        ];
+        let mut bytes = Vec::new();
+        {
+            let mut writer = Transaction::configured_csv_writer_builder().from_writer(&mut bytes);
+            writer.write_record(Transaction::CSV_HEADER).unwrap();
+            for tx in transactions.iter() {
+                writer.write_record(tx.to_csv_record()).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        let mut reader = Transaction::configured_csv_reader_builder().from_reader(bytes.as_slice());
+        let read: Vec<_> = Transaction::read_csv(&mut reader)
+            .unwrap()
+            .collect::<csv::Result<_>>()
+            .unwrap();
+        assert_eq!(read, transactions);
+    }
+
+    #[test]
+    fn constructors() {
+        let amount = TxAmount(Dec!(1.0));
+        assert_eq!(
+            Transaction::new_deposit(ClientId(1), TxId(2), amount),
+            Transaction::Deposit(Deposit {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount
+            })
+        );
+        assert_eq!(
+            Transaction::new_chargeback(ClientId(1), TxId(2)),
+            Transaction::Chargeback(Chargeback {
+                client: ClientId(1),
+                tx: TxId(2),
+            })
+        );
+        assert_eq!(
+            Transaction::new_transfer(ClientId(1), ClientId(3), TxId(2), amount),
+            Transaction::Transfer(Transfer {
+                from: ClientId(1),
+                to: ClientId(3),
+                tx: TxId(2),
+                amount
+            })
+        );
+    }
+
     #[test]
     fn deserialize_transactions() {
         let data = concat!(